@@ -9,8 +9,17 @@
     future_incompatible,
     rust_2018_idioms,
 )]
-#![allow(clippy::option_if_let_else, clippy::module_name_repetitions)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::result_large_err,
+    // `Error::Http` is much larger than the other variants, but errors are
+    // only created on failure and are not stored in bulk.
+    clippy::large_enum_variant,
+    clippy::multiple_crate_versions
+)]
 
+mod manifest;
 mod rustme;
 
 pub use self::rustme::*;
@@ -1,10 +1,77 @@
-use crate::rustme::generate;
+use std::path::Path;
 
-pub mod rustme;
+use rustme::{discover_configurations, generate_with, GenerateOptions};
 
 fn main() {
-    if let Err(err) = generate(std::env::args().nth(1).as_deref() == Some("--release")) {
-        eprintln!("{}", err);
-        std::process::exit(1);
+    let mut options = GenerateOptions::default();
+    let mut list = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--release" {
+            options = options.with_release(true);
+        } else if arg == "--list" {
+            list = true;
+        } else if arg == "--keep-going" {
+            options = options.with_keep_going(true);
+        } else if arg == "--no-ignore" {
+            options.discovery = options.discovery.with_ignore_files(false);
+        } else if let Some(profile) = value_of("--profile", &arg, &mut args) {
+            options = options.with_profile(profile);
+        } else if let Some(definition) = value_of("--define", &arg, &mut args) {
+            options = define(options, &definition);
+        } else if let Some(glob) = value_of("--include", &arg, &mut args) {
+            options.discovery = options.discovery.with_include(glob);
+        } else if let Some(glob) = value_of("--exclude", &arg, &mut args) {
+            options.discovery = options.discovery.with_exclude(glob);
+        } else if let Some(depth) = value_of("--max-depth", &arg, &mut args) {
+            let Ok(depth) = depth.parse() else {
+                exit_with_error(format!("invalid --max-depth: {depth}"));
+            };
+            options.discovery = options.discovery.with_max_depth(depth);
+        } else {
+            exit_with_error(format!("unknown argument: {arg}"));
+        }
     }
+
+    if list {
+        match discover_configurations(Path::new("."), &options.discovery) {
+            Ok(configurations) => {
+                for configuration in configurations {
+                    println!("{}", configuration.display());
+                }
+            }
+            Err(err) => exit_with_error(err),
+        }
+    } else if let Err(err) = generate_with(&options) {
+        exit_with_error(err);
+    }
+}
+
+/// Returns the value of the option `name` if `arg` is the option, accepting
+/// both `--name value` and `--name=value`.
+fn value_of(name: &str, arg: &str, args: &mut impl Iterator<Item = String>) -> Option<String> {
+    if arg == name {
+        let Some(value) = args.next() else {
+            exit_with_error(format!("{name} requires a value"));
+        };
+        Some(value)
+    } else {
+        arg.strip_prefix(name)
+            .and_then(|value| value.strip_prefix('='))
+            .map(String::from)
+    }
+}
+
+fn define(options: GenerateOptions, definition: &str) -> GenerateOptions {
+    let Some((name, value)) = definition.split_once('=') else {
+        exit_with_error(format!(
+            "invalid definition, expected KEY=VALUE: {definition}"
+        ));
+    };
+    options.with_definition(name, value)
+}
+
+fn exit_with_error(error: impl std::fmt::Display) -> ! {
+    eprintln!("{error}");
+    std::process::exit(1);
 }
@@ -0,0 +1,623 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+use serde::{de::DeserializeOwned, Deserialize};
+use toml::Value;
+
+use crate::{Error, Term};
+
+/// A Cargo package whose manifest is used to provide glossary terms.
+#[derive(Debug, Clone)]
+pub struct Package {
+    /// The package's `Cargo.toml`.
+    pub manifest: Manifest,
+    /// The `Cargo.toml` of the workspace containing this package, if any.
+    pub workspace: Option<Manifest>,
+}
+
+/// A Cargo workspace.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    /// The directory containing the workspace's `Cargo.toml`.
+    pub directory: PathBuf,
+    /// The workspace's `Cargo.toml`.
+    pub manifest: Manifest,
+}
+
+/// A member of a [`Workspace`].
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// The path of the member, relative to the workspace root, using `/` as
+    /// the separator.
+    pub path: String,
+    /// The member's package.
+    pub package: Package,
+}
+
+impl Workspace {
+    /// Searches `directory` and its ancestors for the closest `Cargo.toml`
+    /// that defines a workspace.
+    pub fn find(directory: &Path) -> Result<Option<Self>, Error> {
+        let directory = absolute(directory)?;
+        for directory in directory.ancestors() {
+            if let Some(manifest) = read_manifest(directory)? {
+                if manifest.get("workspace").is_some() {
+                    return Ok(Some(Self {
+                        directory: directory.to_path_buf(),
+                        manifest,
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the members of this workspace, expanding any wildcards in
+    /// `workspace.members`. Like Cargo, members located within a path listed
+    /// in `workspace.exclude` are removed. If the workspace manifest also
+    /// defines a package, it is included with a path of `.`.
+    pub fn members(&self) -> Result<Vec<Member>, Error> {
+        let patterns = |key: &str| {
+            self.manifest
+                .get("workspace")
+                .and_then(|workspace| workspace.get(key))
+                .and_then(Value::as_array)
+                .map(|patterns| {
+                    patterns
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|pattern| pattern.trim_end_matches('/').to_string())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        let excluded = patterns("exclude");
+
+        let mut paths = BTreeSet::new();
+        if self.manifest.get("package").is_some() {
+            paths.insert(String::from("."));
+        }
+        for pattern in patterns("members") {
+            for path in expand_pattern(&self.directory, &pattern)? {
+                if !is_excluded(&path, &excluded) {
+                    paths.insert(path);
+                }
+            }
+        }
+
+        let mut members = Vec::new();
+        for path in paths {
+            let manifest = if path == "." {
+                Some(self.manifest.clone())
+            } else {
+                read_manifest(&self.directory.join(&path))?
+            };
+            if let Some(manifest) = manifest.filter(|manifest| manifest.get("package").is_some()) {
+                members.push(Member {
+                    path,
+                    package: Package {
+                        manifest,
+                        workspace: Some(self.manifest.clone()),
+                    },
+                });
+            }
+        }
+        Ok(members)
+    }
+}
+
+/// Expands `*` and `?` wildcards in each component of `pattern`, returning
+/// the matching directories relative to `root`.
+fn expand_pattern(root: &Path, pattern: &str) -> Result<Vec<String>, Error> {
+    let mut paths = vec![String::new()];
+    for component in pattern.split('/').filter(|component| !component.is_empty()) {
+        let mut expanded = Vec::new();
+        for path in paths {
+            let join = |name: &str| {
+                if path.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{path}/{name}")
+                }
+            };
+            if component.contains(['*', '?']) {
+                let entries = match fs::read_dir(root.join(&path)) {
+                    Ok(entries) => entries,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(Error::from(err)),
+                };
+                for entry in entries {
+                    let entry = entry?;
+                    if entry.file_type()?.is_dir() {
+                        if let Some(name) = entry.file_name().to_str() {
+                            if wildcard_matches(component, name) {
+                                expanded.push(join(name));
+                            }
+                        }
+                    }
+                }
+            } else {
+                expanded.push(join(component));
+            }
+        }
+        paths = expanded;
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Returns true if `path` is within one of the `excluded` paths.
+fn is_excluded(path: &str, excluded: &[String]) -> bool {
+    excluded
+        .iter()
+        .any(|excluded| Path::new(path).starts_with(excluded.trim_start_matches("./")))
+}
+
+fn wildcard_matches(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => {
+            let rest = &pattern[1..];
+            name.char_indices()
+                .map(|(index, _)| index)
+                .chain(Some(name.len()))
+                .any(|index| wildcard_matches(rest, &name[index..]))
+        }
+        Some(ch) => name.chars().next().is_some_and(|name_ch| {
+            (ch == '?' || ch == name_ch)
+                && wildcard_matches(&pattern[ch.len_utf8()..], &name[name_ch.len_utf8()..])
+        }),
+    }
+}
+
+/// A parsed `Cargo.toml`.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// The contents of the file, which are used to read comments.
+    pub source: String,
+    /// The parsed contents of the file.
+    pub value: Value,
+}
+
+impl Manifest {
+    pub fn parse(source: String) -> Result<Self, Error> {
+        let value = toml::from_str(&source)?;
+        Ok(Self { source, value })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.value.get(key)
+    }
+
+    /// Deserializes `package.metadata.rustme`, or `workspace.metadata.rustme`
+    /// if the package does not define it. The metadata is deserialized from
+    /// the source rather than [`Manifest::value`] to preserve the order of
+    /// its tables.
+    pub fn rustme_metadata<T: DeserializeOwned>(&self) -> Result<Option<T>, Error> {
+        #[derive(Deserialize)]
+        struct Root<T> {
+            package: Option<Table<T>>,
+            workspace: Option<Table<T>>,
+        }
+
+        #[derive(Deserialize)]
+        struct Table<T> {
+            metadata: Option<Metadata<T>>,
+        }
+
+        #[derive(Deserialize)]
+        struct Metadata<T> {
+            rustme: Option<T>,
+        }
+
+        let root = toml::from_str::<Root<T>>(&self.source)?;
+        Ok([root.package, root.workspace]
+            .into_iter()
+            .find_map(|table| table?.metadata?.rustme))
+    }
+}
+
+impl Package {
+    /// Searches `directory` and its ancestors for the closest `Cargo.toml`,
+    /// returning the package it defines. Returns `None` if no manifest is
+    /// found, or if the closest manifest does not define a package.
+    pub fn find(directory: &Path) -> Result<Option<Self>, Error> {
+        let directory = absolute(directory)?;
+        for directory in directory.ancestors() {
+            if let Some(manifest) = read_manifest(directory)? {
+                return if manifest.get("package").is_some() {
+                    Self::load(directory, manifest).map(Some)
+                } else {
+                    Ok(None)
+                };
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn load(directory: &Path, manifest: Manifest) -> Result<Self, Error> {
+        let workspace = if manifest.get("workspace").is_some() {
+            Some(manifest.clone())
+        } else {
+            find_workspace(directory)?
+        };
+
+        Ok(Self {
+            manifest,
+            workspace,
+        })
+    }
+
+    /// Returns the string value of `package.<field>`, resolving values
+    /// inherited from the workspace using `field.workspace = true`.
+    pub fn field(&self, field: &str) -> Option<&str> {
+        let value = self.manifest.get("package")?.get(field)?;
+        if value
+            .get("workspace")
+            .and_then(Value::as_bool)
+            .unwrap_or_default()
+        {
+            self.workspace
+                .as_ref()?
+                .get("workspace")?
+                .get("package")?
+                .get(field)?
+                .as_str()
+        } else {
+            value.as_str()
+        }
+    }
+
+    /// Returns the glossary terms that are provided automatically for this
+    /// package.
+    pub fn glossary_terms(&self) -> Vec<(String, Term)> {
+        let mut terms = Vec::new();
+        let mut add_term = |name: &str, value: Option<&str>| {
+            if let Some(value) = value {
+                terms.push((name.to_string(), Term::Static(value.to_string())));
+            }
+        };
+        add_term("CRATE_NAME", self.field("name"));
+        add_term("CRATE_VERSION", self.field("version"));
+        add_term("MSRV", self.field("rust-version"));
+        add_term("LICENSE", self.field("license"));
+        add_term("REPOSITORY", self.field("repository"));
+        add_term("DESCRIPTION", self.field("description"));
+        add_term(
+            "DOCS_RS",
+            self.field("name")
+                .map(|name| format!("https://docs.rs/{name}"))
+                .as_deref(),
+        );
+        terms
+    }
+
+    /// Returns a markdown table describing the package's features in the
+    /// order they are declared. Each feature's description is read from the
+    /// comments directly above it in `Cargo.toml`. Returns an empty string if
+    /// the package has no features.
+    pub fn features_table(&self) -> String {
+        // The features are deserialized from the source rather than
+        // [`Manifest::value`] to preserve their order.
+        #[derive(Deserialize)]
+        struct Root {
+            #[serde(default)]
+            features: IndexMap<String, Vec<String>>,
+        }
+
+        let features = toml::from_str::<Root>(&self.manifest.source)
+            .map(|root| root.features)
+            .unwrap_or_default();
+        if features.keys().all(|name| name == "default") {
+            return String::new();
+        }
+
+        let mut enabled_by_default = BTreeSet::new();
+        let mut to_visit = vec!["default"];
+        while let Some(feature) = to_visit.pop() {
+            for enabled in features.get(feature).into_iter().flatten() {
+                if features.contains_key(enabled) && enabled_by_default.insert(enabled.as_str()) {
+                    to_visit.push(enabled);
+                }
+            }
+        }
+
+        let descriptions = feature_descriptions(&self.manifest.source);
+        let mut table = vec![
+            String::from("| Feature | Description | Enables | Default |"),
+            String::from("|---------|-------------|---------|---------|"),
+        ];
+        for (name, enables) in features.iter().filter(|(name, _)| **name != "default") {
+            let enables = enables
+                .iter()
+                .map(|enabled| format!("`{enabled}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let description = descriptions
+                .get(name.as_str())
+                .map(|description| description.replace('|', "\\|"))
+                .unwrap_or_default();
+            let default = if enabled_by_default.contains(name.as_str()) {
+                "yes"
+            } else {
+                "no"
+            };
+            table.push(format!(
+                "| `{name}` | {description} | {enables} | {default} |"
+            ));
+        }
+        table.join("\n")
+    }
+
+    /// Returns a `[dependencies]` section that depends on this package.
+    pub fn dependency_snippet(
+        &self,
+        precision: VersionPrecision,
+        features: &[String],
+        default_features: bool,
+    ) -> String {
+        let name = self.field("name").unwrap_or_default();
+        let version = precision.apply(self.field("version").unwrap_or("0.0.0"));
+        if features.is_empty() && default_features {
+            format!("[dependencies]\n{name} = \"{version}\"")
+        } else {
+            let mut options = vec![format!("version = \"{version}\"")];
+            if !default_features {
+                options.push(String::from("default-features = false"));
+            }
+            if !features.is_empty() {
+                let features = features
+                    .iter()
+                    .map(|feature| format!("\"{feature}\""))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                options.push(format!("features = [{features}]"));
+            }
+            let options = options.join(", ");
+            format!("[dependencies]\n{name} = {{ {options} }}")
+        }
+    }
+}
+
+/// The number of version components to include in a dependency requirement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionPrecision {
+    /// `1`
+    Major,
+    /// `1.2`
+    Minor,
+    /// `1.2.3`
+    Patch,
+}
+
+impl VersionPrecision {
+    pub fn parse(precision: &str) -> Option<Self> {
+        match precision {
+            "major" | "1" => Some(Self::Major),
+            "minor" | "2" => Some(Self::Minor),
+            "patch" | "3" => Some(Self::Patch),
+            _ => None,
+        }
+    }
+
+    /// Returns `version` with only the components allowed by this precision.
+    /// Pre-release versions are returned unmodified, as a requirement without
+    /// the pre-release identifier would not match them.
+    pub fn apply(self, version: &str) -> &str {
+        if version.contains(['-', '+']) {
+            return version;
+        }
+
+        let components = match self {
+            Self::Major => 1,
+            Self::Minor => 2,
+            Self::Patch => 3,
+        };
+        version
+            .match_indices('.')
+            .nth(components - 1)
+            .map_or(version, |(index, _)| &version[..index])
+    }
+}
+
+/// Returns the comments directly above each feature in the `[features]`
+/// table of `source`, keyed by feature name.
+fn feature_descriptions(source: &str) -> BTreeMap<String, String> {
+    let mut descriptions = BTreeMap::new();
+    let mut in_features = false;
+    let mut comments = Vec::new();
+    let mut array_depth = 0_usize;
+    for line in source.lines() {
+        let line = line.trim();
+        if array_depth > 0 {
+            array_depth =
+                (array_depth + line.matches('[').count()).saturating_sub(line.matches(']').count());
+            continue;
+        } else if line.starts_with('[') {
+            in_features = line == "[features]";
+            comments.clear();
+            continue;
+        } else if !in_features {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            comments.push(comment.trim());
+        } else if let Some((name, value)) = line.split_once('=') {
+            let name = name.trim().trim_matches('"');
+            if !comments.is_empty() {
+                descriptions.insert(name.to_string(), comments.join(" "));
+            }
+            comments.clear();
+            array_depth = value
+                .matches('[')
+                .count()
+                .saturating_sub(value.matches(']').count());
+        } else {
+            comments.clear();
+        }
+    }
+    descriptions
+}
+
+fn absolute(directory: &Path) -> Result<PathBuf, Error> {
+    if directory.as_os_str().is_empty() {
+        Ok(std::env::current_dir()?)
+    } else {
+        Ok(directory.canonicalize()?)
+    }
+}
+
+fn find_workspace(package_directory: &Path) -> Result<Option<Manifest>, Error> {
+    for directory in package_directory.ancestors().skip(1) {
+        if let Some(manifest) = read_manifest(directory)? {
+            if manifest.get("workspace").is_some() {
+                return Ok(Some(manifest));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_manifest(directory: &Path) -> Result<Option<Manifest>, Error> {
+    match fs::read_to_string(directory.join("Cargo.toml")) {
+        Ok(contents) => Manifest::parse(contents).map(Some),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::from(err)),
+    }
+}
+
+#[test]
+fn glossary_terms_tests() {
+    let package = Package {
+        manifest: Manifest::parse(String::from(
+            r#"
+            [package]
+            name = "member"
+            version = "0.2.0"
+            rust-version = "1.56"
+            license.workspace = true
+            description = "A workspace member"
+            "#,
+        ))
+        .unwrap(),
+        workspace: Some(
+            Manifest::parse(String::from(
+                r#"
+                [workspace]
+                members = ["member"]
+
+                [workspace.package]
+                license = "MIT OR Apache-2.0"
+                "#,
+            ))
+            .unwrap(),
+        ),
+    };
+    let terms = package
+        .glossary_terms()
+        .into_iter()
+        .map(|(name, term)| match term {
+            Term::Static(value) => (name, value),
+            Term::Conditional { .. } => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    let term = |name: &str| {
+        terms
+            .iter()
+            .find(|(term_name, _)| term_name == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(term("CRATE_NAME"), Some("member"));
+    assert_eq!(term("CRATE_VERSION"), Some("0.2.0"));
+    assert_eq!(term("MSRV"), Some("1.56"));
+    assert_eq!(term("LICENSE"), Some("MIT OR Apache-2.0"));
+    assert_eq!(term("REPOSITORY"), None);
+    assert_eq!(term("DESCRIPTION"), Some("A workspace member"));
+    assert_eq!(term("DOCS_RS"), Some("https://docs.rs/member"));
+}
+
+#[test]
+fn version_precision_tests() {
+    assert_eq!(VersionPrecision::Major.apply("1.2.3"), "1");
+    assert_eq!(VersionPrecision::Minor.apply("1.2.3"), "1.2");
+    assert_eq!(VersionPrecision::Patch.apply("1.2.3"), "1.2.3");
+    assert_eq!(VersionPrecision::Minor.apply("1"), "1");
+    assert_eq!(
+        VersionPrecision::Minor.apply("0.2.0-alpha.1"),
+        "0.2.0-alpha.1"
+    );
+}
+
+#[test]
+fn features_table_tests() {
+    let package = Package {
+        manifest: Manifest::parse(String::from(
+            r#"
+            [package]
+            name = "example"
+
+            [features]
+            default = ["std"]
+            # Enables support for the standard library.
+            std = ["alloc"]
+            # Enables types that require an allocator.
+            # Implied by `std`.
+            alloc = []
+
+            serde = [
+                "dep:serde", # not a description
+            ]
+            # Combines a | b
+            "both" = ["std", "serde"]
+
+            [dependencies]
+            # Unrelated comment
+            serde = { version = "1", optional = true }
+            "#,
+        ))
+        .unwrap(),
+        workspace: None,
+    };
+    assert_eq!(
+        package.features_table(),
+        "| Feature | Description | Enables | Default |
+|---------|-------------|---------|---------|
+| `std` | Enables support for the standard library. | `alloc` | yes |
+| `alloc` | Enables types that require an allocator. Implied by `std`. |  | yes |
+| `serde` |  | `dep:serde` | no |
+| `both` | Combines a \\| b | `std`, `serde` | no |"
+    );
+
+    let package = Package {
+        manifest: Manifest::parse(String::from("[package]\nname = \"example\"\n")).unwrap(),
+        workspace: None,
+    };
+    assert_eq!(package.features_table(), "");
+}
+
+#[test]
+fn wildcard_tests() {
+    assert!(wildcard_matches("*", "anything"));
+    assert!(wildcard_matches("rustme-*", "rustme-core"));
+    assert!(!wildcard_matches("rustme-*", "bonsaidb"));
+    assert!(wildcard_matches("crate?", "crate1"));
+    assert!(!wildcard_matches("crate?", "crate12"));
+}
+
+#[test]
+fn exclude_tests() {
+    let excluded = [String::from("crates"), String::from("./examples/old")];
+    assert!(is_excluded("crates", &excluded));
+    assert!(is_excluded("crates/a", &excluded));
+    assert!(is_excluded("examples/old", &excluded));
+    assert!(!is_excluded("crates-extra", &excluded));
+    assert!(!is_excluded("examples/new", &excluded));
+}
@@ -1,35 +1,72 @@
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
-    io::{ErrorKind, Write},
+    io::ErrorKind,
+    ops::Range,
     path::{Path, PathBuf},
     str::Utf8Error,
     string::FromUtf8Error,
 };
 
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use indexmap::IndexMap;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
 use serde::{Deserialize, Serialize};
-use walkdir::WalkDir;
+
+use crate::manifest::{Manifest, Package, VersionPrecision, Workspace};
 
 /// A configuration of how to generate one or more READMEs.
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Configuration {
     /// The location that paths should be resolved relative to.
     #[serde(skip)]
     pub relative_to: PathBuf,
-    /// The collection of files (key) and sections (values).
-    pub files: HashMap<String, FileConfiguration>,
+    /// A path or Url of another configuration to inherit from. A relative
+    /// path is resolved relative to the configuration that contains it.
+    ///
+    /// The extended configuration's [`glossaries`](Self::glossaries) are
+    /// loaded before this configuration's, allowing terms to be overridden.
+    /// Its [`files`](Self::files) are generated before this configuration's
+    /// files, unless this configuration declares a file with the same name,
+    /// which replaces the inherited definition. Inherited sections and
+    /// glossaries are resolved relative to this configuration.
+    ///
+    /// The extended configuration is parsed as TOML if its name ends in
+    /// `.toml`, and as Ron otherwise. It may extend another configuration. In
+    /// Ron, this is written as `extends: Some("../shared/config.ron")`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// The collection of files (key) and sections (values). Files are
+    /// generated in the order they are declared, except that a file whose
+    /// section is generated by another file, including a file from another
+    /// configuration processed in the same run, is generated after it.
+    ///
+    /// If a key contains `{member}`, the file is generated once for each
+    /// member of the Cargo workspace containing this configuration. `{member}`
+    /// is replaced with the member's path relative to the workspace root in
+    /// both the key and the file's section paths, and those paths are
+    /// resolved relative to the workspace root rather than this
+    /// configuration. The glossary terms provided
+    /// from `Cargo.toml`, such as `CRATE_NAME`, are read from each member's
+    /// manifest, and `MEMBER_PATH` contains the member's path.
+    #[serde(default)]
+    pub files: IndexMap<String, FileConfiguration>,
     /// A list of glossaries that act as a source of snippets.
     #[serde(default)]
     pub glossaries: Vec<Glossary>,
+    /// The delimiters used for references. Defaults to `$name$`.
+    #[serde(default)]
+    pub reference_delimiters: ReferenceDelimiters,
 }
 
 /// A configuration for a [`File`].
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum FileConfiguration {
     /// An inline file configuration, which is just a list of sections.
-    Sections(Vec<String>),
+    Sections(Vec<Section>),
     /// A full file configuration.
     File(File),
 }
@@ -49,11 +86,35 @@ impl<'a> From<&'a FileConfiguration> for File {
 /// A `RustMe` file configuration.
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct File {
-    /// If true, the output is considered for `cargo doc`.
+    /// If true, the output is considered for `cargo doc`. Hidden lines in Rust
+    /// code blocks are preserved so that rustdoc can compile the doctests.
     #[serde(default)]
     pub for_docs: bool,
+    /// If true, snippets loaded from files with a recognized extension are
+    /// automatically wrapped in a code fence tagged with the file's language.
+    /// Only references that are alone on their line outside of existing code
+    /// are fenced.
+    #[serde(default)]
+    pub fence_snippets: bool,
+    /// If true, code blocks and inline code are left verbatim. References
+    /// and conditional directives within code must be written explicitly,
+    /// such as `$rustme:name$`, to be expanded. This allows code such as
+    /// `echo $HOME` to be written without escaping.
+    #[serde(default)]
+    pub verbatim_code: bool,
+    /// The delimiters used for references in this file. If not specified,
+    /// [`Configuration::reference_delimiters`] is used.
+    #[serde(default)]
+    pub reference_delimiters: Option<ReferenceDelimiters>,
+    /// A list of profiles that are active when rendering this file, in
+    /// addition to the profiles specified in [`GenerateOptions`]. Profiles
+    /// select [`Term`] values and can be checked in conditional blocks, such
+    /// as `$if github$ ... $endif$`. Profiles listed first have a higher
+    /// precedence.
+    #[serde(default)]
+    pub profiles: Vec<String>,
     /// A list of sections that compose this file.
-    pub sections: Vec<String>,
+    pub sections: Vec<Section>,
     /// A list of glossaries that are used for this file. Any [`Term`]s defined
     /// in these glossaries will have a higher precedence than the ones defined
     /// at the [`Configuration`] level.
@@ -61,6 +122,90 @@ pub struct File {
     pub glossaries: Vec<Glossary>,
 }
 
+/// A section of a [`File`].
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Section {
+    /// A section that is always included. The value is a path or Url.
+    Path(String),
+    /// A section that is only included when all of its conditions are met.
+    Conditional {
+        /// The path or Url of the section.
+        path: String,
+        /// If true, the section is only included when [`File::for_docs`] is
+        /// true.
+        #[serde(default)]
+        only_for_docs: bool,
+        /// If true, the section is only included when outputting in release
+        /// mode.
+        #[serde(default)]
+        only_release: bool,
+        /// A condition using the same syntax as `$if condition$` blocks, such
+        /// as `"!for_docs"` or the name of a profile.
+        #[serde(default)]
+        condition: Option<String>,
+    },
+}
+
+impl Section {
+    /// Returns the path or Url of this section.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Path(path) | Self::Conditional { path, .. } => path,
+        }
+    }
+
+    /// Replaces `{member}` with the member's path. A path containing
+    /// `{member}` is relative to the workspace's `directory`.
+    fn replace_member_placeholder(&mut self, directory: &Path, member_path: &str) {
+        match self {
+            Self::Path(path) | Self::Conditional { path, .. } => {
+                if path.contains(MEMBER_PLACEHOLDER) {
+                    *path = directory
+                        .join(path.replace(MEMBER_PLACEHOLDER, member_path))
+                        .to_string_lossy()
+                        .into_owned();
+                }
+            }
+        }
+    }
+
+    fn is_included(&self, context: Context<'_>) -> bool {
+        match self {
+            Self::Path(_) => true,
+            Self::Conditional {
+                only_for_docs,
+                only_release,
+                condition,
+                ..
+            } => {
+                (!only_for_docs || context.file.for_docs)
+                    && (!only_release || context.is_profile_active("release"))
+                    && condition
+                        .as_ref()
+                        .is_none_or(|condition| context.is_profile_active(condition.trim()))
+            }
+        }
+    }
+}
+
+/// The placeholder in a [`Configuration::files`] key that is replaced with
+/// the path of each workspace member.
+const MEMBER_PLACEHOLDER: &str = "{member}";
+
+impl From<String> for Section {
+    fn from(path: String) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl<'a> From<&'a str> for Section {
+    fn from(path: &'a str) -> Self {
+        Self::Path(path.to_string())
+    }
+}
+
 impl Configuration {
     /// Attempts to load a configuration from `path`.
     ///
@@ -73,14 +218,34 @@ impl Configuration {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let contents = std::fs::read_to_string(path.as_ref())?;
         let mut configuration = ron::from_str::<Self>(&contents)?;
-        configuration.relative_to = path
-            .as_ref()
-            .parent()
-            .ok_or_else(|| Error::Io(std::io::Error::from(ErrorKind::NotFound)))?
-            .to_path_buf();
+        configuration.relative_to = parent_directory(path.as_ref())?;
         Ok(configuration)
     }
 
+    /// Attempts to load a configuration from the `Cargo.toml` at `path`. The
+    /// configuration is read from `[package.metadata.rustme]`, falling back to
+    /// `[workspace.metadata.rustme]`. Returns `None` if neither table is
+    /// present.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Io`]: Returned if an error occurs interacting with the
+    ///   filesystem.
+    /// - [`Error::Toml`]: Returned if an error occurs while parsing the
+    ///   manifest or the configuration it contains.
+    pub fn load_from_manifest<P: AsRef<Path>>(path: P) -> Result<Option<Self>, Error> {
+        let manifest = Manifest::parse(std::fs::read_to_string(path.as_ref())?)?;
+        let Some(mut configuration) = Self::from_manifest(&manifest)? else {
+            return Ok(None);
+        };
+        configuration.relative_to = parent_directory(path.as_ref())?;
+        Ok(Some(configuration))
+    }
+
+    fn from_manifest(manifest: &Manifest) -> Result<Option<Self>, Error> {
+        manifest.rustme_metadata()
+    }
+
     /// Generates the README files.
     ///
     /// # Errors
@@ -98,55 +263,201 @@ impl Configuration {
     /// Can return various errors that are encountred with files that could not
     /// be parsed.
     pub fn generate_with_cache(&self, release: bool, cache: &mut Cache) -> Result<(), Error> {
-        let mut snippets = HashMap::new();
-        let glossary = self.load_glossaries(cache)?;
+        self.generate_with(&GenerateOptions::default().with_release(release), cache)
+    }
+
+    /// Generates the README files using `options`, using `cache` to load
+    /// glossaries and snippets.
+    ///
+    /// # Errors
+    ///
+    /// Can return various errors that are encountred with files that could not
+    /// be parsed.
+    pub fn generate_with(&self, options: &GenerateOptions, cache: &mut Cache) -> Result<(), Error> {
+        let mut failures = Failures::new(options.keep_going);
+        match self
+            .resolve_extends(cache)
+            .and_then(|configuration| Ok((configuration.plan()?, configuration)))
+        {
+            Ok((files, configuration)) => generate_planned(
+                &[PlannedConfiguration {
+                    source: self.relative_to.clone(),
+                    configuration,
+                    files,
+                }],
+                options,
+                cache,
+                &mut failures,
+            )?,
+            Err(err) => failures.record_in_context(&self.relative_to, None, err)?,
+        }
+        failures.finish()
+    }
+
+    /// Resolves every file this configuration generates. The configuration
+    /// must not have any unresolved [`extends`](Self::extends).
+    fn plan(&self) -> Result<Vec<PlannedFile>, Error> {
+        // Package terms are optional, so a manifest that cannot be read only
+        // causes an error if a built-in reference needs the package.
+        let package = Package::find(&self.relative_to).unwrap_or_else(|err| {
+            eprintln!(
+                "warning: ignoring Cargo.toml for {}: {err}",
+                self.relative_to.display()
+            );
+            None
+        });
+        let workspace = if self
+            .files
+            .keys()
+            .any(|name| name.contains(MEMBER_PLACEHOLDER))
+        {
+            let workspace = Workspace::find(&self.relative_to)?.ok_or(Error::WorkspaceNotFound)?;
+            let members = workspace.members()?;
+            Some((workspace.directory, members))
+        } else {
+            None
+        };
+
+        let mut planned = Vec::new();
         for (name, file_config) in &self.files {
-            let output_path = self.relative_to.join(name);
-            if output_path.exists() {
-                std::fs::remove_file(&output_path)?;
+            let file = File::from(file_config);
+            if let (true, Some((directory, members))) =
+                (name.contains(MEMBER_PLACEHOLDER), &workspace)
+            {
+                for member in members {
+                    let mut file = file.clone();
+                    for section in &mut file.sections {
+                        section.replace_member_placeholder(directory, &member.path);
+                    }
+                    let mut terms = member.package.glossary_terms();
+                    terms.push((
+                        String::from("MEMBER_PATH"),
+                        Term::Static(member.path.clone()),
+                    ));
+                    planned.push(PlannedFile::new(
+                        self,
+                        &directory.join(name.replace(MEMBER_PLACEHOLDER, &member.path)),
+                        file,
+                        Some(member.package.clone()),
+                        terms,
+                    ));
+                }
+            } else {
+                let terms = package
+                    .as_ref()
+                    .map(Package::glossary_terms)
+                    .unwrap_or_default();
+                planned.push(PlannedFile::new(
+                    self,
+                    &self.relative_to.join(name),
+                    file,
+                    package.clone(),
+                    terms,
+                ));
             }
+        }
 
-            let file = File::from(file_config);
+        Ok(planned)
+    }
 
-            let glossary = if file.glossaries.is_empty() {
-                Cow::Borrowed(&glossary)
+    /// Returns a copy of this configuration with the chain of
+    /// [`extends`](Self::extends) merged into it.
+    fn resolve_extends(&self, cache: &mut Cache) -> Result<Self, Error> {
+        let mut resolved = self.clone();
+        let mut visited = Vec::new();
+        let mut location = self.relative_to.clone();
+        while let Some(extends) = resolved.extends.take() {
+            let resource = if is_url(&extends) {
+                extends
+            } else if let Some(base) = location.to_str().filter(|base| is_url(base)) {
+                format!("{base}/{extends}")
             } else {
-                let mut combined_glossary = glossary.clone();
-                self.load_glossaries_into(&file.glossaries, &mut combined_glossary, cache)?;
-                Cow::Owned(combined_glossary)
+                location.join(&extends).to_string_lossy().into_owned()
             };
-
-            let mut output = fs::File::create(&output_path)?;
-            for (index, section) in file.sections.iter().enumerate() {
-                if index > 0 {
-                    output.write_all(b"\n")?;
-                }
-                let markdown = cache.get(section, &self.relative_to, || {
-                    Error::SnippetNotFound(section.to_string())
-                })?;
-                let processed = process_markdown(
-                    &markdown,
-                    &self.relative_to,
-                    &mut snippets,
-                    &glossary,
-                    Context {
-                        release,
-                        for_docs: file.for_docs,
-                    },
-                )?;
-                output.write_all(processed.as_bytes())?;
+            if visited.contains(&resource) {
+                return Err(Error::CyclicExtends(resource));
             }
+
+            // `resource` already includes `location`.
+            let contents = cache.get(&resource, Path::new(""), || {
+                Error::ExtendedConfigurationNotFound(resource.clone())
+            })?;
+            let parent = if Path::new(&resource)
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                toml::from_str::<Self>(&contents)?
+            } else {
+                ron::from_str::<Self>(&contents)?
+            };
+
+            location = match resource.rfind('/') {
+                Some(index) if is_url(&resource) => PathBuf::from(&resource[..index]),
+                _ => parent_directory(Path::new(&resource))?,
+            };
+            visited.push(resource);
+
+            resolved.extends = parent.extends;
+            resolved.glossaries.splice(0..0, parent.glossaries);
+            let files = std::mem::replace(&mut resolved.files, parent.files);
+            resolved.files.extend(files);
         }
 
-        Ok(())
+        Ok(resolved)
     }
 
-    fn load_glossaries(&self, cache: &mut Cache) -> Result<HashMap<String, Term>, Error> {
-        let mut combined = HashMap::new();
+    fn generate_file(
+        &self,
+        planned: &PlannedFile,
+        options: &GenerateOptions,
+        cache: &mut Cache,
+        snippets: &mut HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let file = &planned.file;
+        let delimiters = file
+            .reference_delimiters
+            .as_ref()
+            .unwrap_or(&self.reference_delimiters);
+        delimiters.validate()?;
+
+        let mut glossary = planned.terms.iter().cloned().collect::<HashMap<_, _>>();
+        self.load_glossaries_into(&self.glossaries, &mut glossary, cache)?;
+        self.load_glossaries_into(&file.glossaries, &mut glossary, cache)?;
+        options.apply_definitions(&mut glossary);
+
+        let profiles = options.profiles_for(file);
+        let context = Context {
+            profiles: &profiles,
+            file,
+            delimiters,
+            package: planned.package.as_ref(),
+        };
+        let mut sections = Vec::new();
+        for section in file
+            .sections
+            .iter()
+            .filter(|section| section.is_included(context))
+        {
+            let path = section.path();
+            let processed = cache
+                .get(path, &self.relative_to, || {
+                    Error::SnippetNotFound(path.to_string())
+                })
+                .and_then(|markdown| {
+                    process_markdown(&markdown, &self.relative_to, snippets, &glossary, context)
+                })
+                .map_err(|error| Error::Section {
+                    section: path.to_string(),
+                    error: Box::new(error),
+                })?;
+            sections.push(processed);
+        }
 
-        self.load_glossaries_into(&self.glossaries, &mut combined, cache)?;
+        let contents = sections.join("\n");
+        fs::write(&planned.output, &contents)?;
+        cache.insert_generated(&planned.output, contents);
 
-        Ok(combined)
+        Ok(())
     }
 
     fn load_glossaries_into(
@@ -174,20 +485,21 @@ impl Configuration {
     ) -> Result<(), Error> {
         match glossary {
             Glossary::External(reference) => {
-                let glossary_text =
-                    cache.get(reference, &self.relative_to, || Error::Glossary {
-                        location: reference.to_string(),
+                let resource =
+                    cache.get_resource(reference, &self.relative_to, || Error::Glossary {
+                        location: reference.clone(),
                         error: String::from("not found"),
                     })?;
 
-                let glossary = ron::from_str::<BTreeMap<String, Term>>(&glossary_text)?;
+                let glossary = GlossaryFormat::detect(reference, resource.content_type.as_deref())
+                    .parse(&resource.contents)?;
                 for (key, value) in glossary {
                     merge_term(combined, key, value);
                 }
             }
             Glossary::Inline(glossary) => {
                 for (key, term) in glossary {
-                    merge_term(combined, key.to_string(), term.clone());
+                    merge_term(combined, key.clone(), term.clone());
                 }
             }
         }
@@ -204,9 +516,527 @@ fn merge_term(combined: &mut HashMap<String, Term>, key: String, term: Term) {
     }
 }
 
+/// A file that a [`Configuration`] generates.
+struct PlannedFile {
+    /// The output path, normalized using [`normalize_path`].
+    output: PathBuf,
+    file: File,
+    package: Option<Package>,
+    /// The glossary terms provided by the file's package, which have the
+    /// lowest precedence.
+    terms: Vec<(String, Term)>,
+    /// The normalized paths of the file's sections that are not Urls.
+    inputs: Vec<PathBuf>,
+}
+
+impl PlannedFile {
+    fn new(
+        configuration: &Configuration,
+        output: &Path,
+        file: File,
+        package: Option<Package>,
+        terms: Vec<(String, Term)>,
+    ) -> Self {
+        let inputs = file
+            .sections
+            .iter()
+            .map(Section::path)
+            .filter(|path| !is_url(path))
+            .map(|path| normalize_path(&configuration.relative_to.join(path)))
+            .collect();
+        Self {
+            output: normalize_path(output),
+            file,
+            package,
+            terms,
+            inputs,
+        }
+    }
+}
+
+/// Collects errors when [`GenerateOptions::keep_going`] is set.
+struct Failures {
+    keep_going: bool,
+    failures: Vec<Failure>,
+}
+
+impl Failures {
+    const fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Records `error`, or returns it if errors are not being collected.
+    fn record(
+        &mut self,
+        configuration: &Path,
+        output: Option<&Path>,
+        error: Error,
+    ) -> Result<(), Error> {
+        if self.keep_going {
+            self.failures.push(Failure {
+                configuration: configuration.to_path_buf(),
+                output: output.map(Path::to_path_buf),
+                error,
+            });
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    /// Records `error` like [`Failures::record`]. If errors are not being
+    /// collected, `error` is returned wrapped in the configuration and output
+    /// file it occurred in.
+    fn record_in_context(
+        &mut self,
+        configuration: &Path,
+        output: Option<&Path>,
+        error: Error,
+    ) -> Result<(), Error> {
+        if self.keep_going {
+            self.record(configuration, output, error)
+        } else {
+            let error = match output {
+                Some(output) => Error::Output {
+                    output: output.to_path_buf(),
+                    error: Box::new(error),
+                },
+                None => error,
+            };
+            Err(Error::Configuration {
+                configuration: configuration.to_path_buf(),
+                error: Box::new(error),
+            })
+        }
+    }
+
+    /// Returns [`Error::Multiple`] if any errors were recorded.
+    fn finish(self) -> Result<(), Error> {
+        if self.failures.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Multiple(self.failures))
+        }
+    }
+}
+
+/// An error that occurred while generating with
+/// [`GenerateOptions::keep_going`].
+#[derive(Debug)]
+pub struct Failure {
+    /// The configuration that was being processed.
+    pub configuration: PathBuf,
+    /// The output file that was being generated, if the error was specific
+    /// to a single file.
+    pub output: Option<PathBuf>,
+    /// The error that occurred.
+    pub error: Error,
+}
+
+/// A [`Configuration`] and the files it generates.
+struct PlannedConfiguration {
+    /// The path the configuration was loaded from, used in errors.
+    source: PathBuf,
+    configuration: Configuration,
+    files: Vec<PlannedFile>,
+}
+
+/// Generates every planned file, ordering the files such that a file that
+/// uses another generated file as a section is generated after it.
+fn generate_planned(
+    configurations: &[PlannedConfiguration],
+    options: &GenerateOptions,
+    cache: &mut Cache,
+    failures: &mut Failures,
+) -> Result<(), Error> {
+    let skipped = check_conflicts(configurations, failures)?;
+    let order = dependency_order(configurations, failures)?;
+
+    // Outputs that will not be generated successfully. Files that conflict
+    // with another file or are part of a cycle are known to fail up front.
+    let ordered = order.iter().copied().collect::<HashSet<_>>();
+    let mut failed = HashSet::<&Path>::new();
+    for (config_index, planned) in configurations.iter().enumerate() {
+        for (file_index, file) in planned.files.iter().enumerate() {
+            let node = (config_index, file_index);
+            if skipped.contains(&node) || !ordered.contains(&node) {
+                failed.insert(&file.output);
+            }
+        }
+    }
+
+    let mut snippets = vec![HashMap::new(); configurations.len()];
+    for node in order {
+        if skipped.contains(&node) {
+            continue;
+        }
+        let (config_index, file_index) = node;
+        let planned = &configurations[config_index];
+        let file = &planned.files[file_index];
+        if let Some(input) = file
+            .inputs
+            .iter()
+            .find(|input| failed.contains(input.as_path()))
+        {
+            failures.record(
+                &planned.source,
+                Some(&file.output),
+                Error::DependencyFailed(input.clone()),
+            )?;
+            failed.insert(&file.output);
+        } else if let Err(err) =
+            planned
+                .configuration
+                .generate_file(file, options, cache, &mut snippets[config_index])
+        {
+            failures.record_in_context(&planned.source, Some(&file.output), err)?;
+            failed.insert(&file.output);
+        }
+    }
+    Ok(())
+}
+
+/// Returns [`Error::OutputConflict`] if more than one planned file writes to
+/// the same path. If `failures` is collecting errors, each conflict is
+/// recorded instead, and the files that should be skipped are returned.
+fn check_conflicts(
+    configurations: &[PlannedConfiguration],
+    failures: &mut Failures,
+) -> Result<HashSet<(usize, usize)>, Error> {
+    let mut outputs = HashMap::<&Path, &Path>::new();
+    let mut skipped = HashSet::new();
+    for (config_index, planned) in configurations.iter().enumerate() {
+        for (file_index, file) in planned.files.iter().enumerate() {
+            if let Some(&first) = outputs.get(file.output.as_path()) {
+                failures.record(
+                    &planned.source,
+                    Some(&file.output),
+                    Error::OutputConflict {
+                        output: file.output.clone(),
+                        first: first.to_path_buf(),
+                        second: planned.source.clone(),
+                    },
+                )?;
+                skipped.insert((config_index, file_index));
+            } else {
+                outputs.insert(&file.output, &planned.source);
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+/// Returns the order to generate the planned files in, as indexes into
+/// `configurations` and their planned files. Files are generated in the order
+/// they are declared unless they depend on another file's output. If
+/// `failures` is collecting errors, files that are part of a cycle are
+/// recorded and left out of the order.
+fn dependency_order(
+    configurations: &[PlannedConfiguration],
+    failures: &mut Failures,
+) -> Result<Vec<(usize, usize)>, Error> {
+    #[derive(Clone, Copy, Eq, PartialEq)]
+    enum State {
+        Pending,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        node: (usize, usize),
+        configurations: &[PlannedConfiguration],
+        outputs: &HashMap<&Path, (usize, usize)>,
+        states: &mut HashMap<(usize, usize), State>,
+        stack: &mut Vec<(usize, usize)>,
+        order: &mut Vec<(usize, usize)>,
+    ) -> Result<(), Error> {
+        match states.get(&node).copied().unwrap_or(State::Pending) {
+            State::Done => return Ok(()),
+            State::Visiting => {
+                let start = stack
+                    .iter()
+                    .position(|visiting| *visiting == node)
+                    .unwrap_or(0);
+                let cycle = stack[start..]
+                    .iter()
+                    .chain(Some(&node))
+                    .map(|&(config, file)| configurations[config].files[file].output.clone())
+                    .collect();
+                return Err(Error::CyclicDependency(cycle));
+            }
+            State::Pending => {}
+        }
+
+        states.insert(node, State::Visiting);
+        stack.push(node);
+        for input in &configurations[node.0].files[node.1].inputs {
+            if let Some(&dependency) = outputs.get(input.as_path()) {
+                visit(dependency, configurations, outputs, states, stack, order)?;
+            }
+        }
+        stack.pop();
+        states.insert(node, State::Done);
+        order.push(node);
+        Ok(())
+    }
+
+    let nodes = configurations
+        .iter()
+        .enumerate()
+        .flat_map(|(config, planned)| (0..planned.files.len()).map(move |file| (config, file)))
+        .collect::<Vec<_>>();
+    let outputs = nodes
+        .iter()
+        .rev()
+        .map(|&(config, file)| {
+            (
+                configurations[config].files[file].output.as_path(),
+                (config, file),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut states = HashMap::new();
+    let mut order = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let mut stack = Vec::new();
+        if let Err(err) = visit(
+            node,
+            configurations,
+            &outputs,
+            &mut states,
+            &mut stack,
+            &mut order,
+        ) {
+            let planned = &configurations[node.0];
+            failures.record(&planned.source, Some(&planned.files[node.1].output), err)?;
+            for node in stack {
+                states.insert(node, State::Done);
+            }
+        }
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+fn planned(relative_to: &str, files: &[(&str, &[&str])]) -> PlannedConfiguration {
+    let configuration = Configuration {
+        relative_to: PathBuf::from(relative_to),
+        extends: None,
+        files: IndexMap::new(),
+        glossaries: Vec::new(),
+        reference_delimiters: ReferenceDelimiters::default(),
+    };
+    let planned = files
+        .iter()
+        .map(|(name, sections)| {
+            let file = File {
+                sections: sections.iter().copied().map(Section::from).collect(),
+                ..File::default()
+            };
+            PlannedFile::new(
+                &configuration,
+                &configuration.relative_to.join(name),
+                file,
+                None,
+                Vec::new(),
+            )
+        })
+        .collect();
+    PlannedConfiguration {
+        source: configuration.relative_to.clone(),
+        configuration,
+        files: planned,
+    }
+}
+
+#[test]
+fn dependency_order_tests() {
+    let configurations = [
+        planned(
+            "/project/.rustme",
+            &[
+                ("../README.md", &["header.md", "../src/docs.md"]),
+                (
+                    "../CONTRIBUTING.md",
+                    &["https://example.com/CONTRIBUTING.md"],
+                ),
+            ],
+        ),
+        planned("/project/src", &[("docs.md", &["./intro.md"])]),
+    ];
+    assert_eq!(
+        dependency_order(&configurations, &mut Failures::new(false)).unwrap(),
+        [(1, 0), (0, 0), (0, 1)]
+    );
+
+    let cyclic = [planned(
+        "/project",
+        &[("a.md", &["b.md"]), ("b.md", &["./a.md"])],
+    )];
+    let Err(Error::CyclicDependency(cycle)) = dependency_order(&cyclic, &mut Failures::new(false))
+    else {
+        unreachable!("cycle not detected")
+    };
+    assert_eq!(
+        cycle,
+        [
+            PathBuf::from("/project/a.md"),
+            PathBuf::from("/project/b.md"),
+            PathBuf::from("/project/a.md")
+        ]
+    );
+}
+
+#[test]
+fn output_conflict_tests() {
+    let configurations = [
+        planned("/project/.rustme", &[("../README.md", &["header.md"])]),
+        planned("/project", &[("docs.md", &["intro.md"])]),
+    ];
+    assert!(check_conflicts(&configurations, &mut Failures::new(false)).is_ok());
+
+    let configurations = [
+        planned("/project/.rustme", &[("../README.md", &["header.md"])]),
+        planned("/project/sub", &[("../README.md", &["intro.md"])]),
+    ];
+    let Err(Error::OutputConflict {
+        output,
+        first,
+        second,
+    }) = check_conflicts(&configurations, &mut Failures::new(false))
+    else {
+        unreachable!("conflict not detected")
+    };
+    assert_eq!(output, Path::new("/project/README.md"));
+    assert_eq!(first, Path::new("/project/.rustme"));
+    assert_eq!(second, Path::new("/project/sub"));
+}
+
+#[test]
+fn keep_going_tests() {
+    let configurations = [
+        planned(
+            "/project/.rustme",
+            &[("../README.md", &["header.md"]), ("../a.md", &["../b.md"])],
+        ),
+        planned(
+            "/project/sub",
+            &[("../README.md", &["intro.md"]), ("../b.md", &["../a.md"])],
+        ),
+    ];
+    let mut failures = Failures::new(true);
+    let skipped = check_conflicts(&configurations, &mut failures).unwrap();
+    assert_eq!(skipped, HashSet::from([(1, 0)]));
+    let order = dependency_order(&configurations, &mut failures).unwrap();
+    assert_eq!(order, [(0, 0), (1, 0)]);
+
+    let Err(error) = failures.finish() else {
+        unreachable!("failures not returned")
+    };
+    assert_eq!(
+        error.to_string(),
+        "2 errors occurred
+/project/sub:
+  /project/README.md:
+    /project/README.md is generated by both /project/.rustme and /project/sub
+/project/.rustme:
+  /project/a.md:
+    generated files depend on each other: /project/a.md -> /project/b.md -> /project/a.md"
+    );
+}
+
+#[test]
+fn failed_dependency_tests() {
+    let directory = TestDirectory::new("failed-dependency");
+    fs::create_dir_all(directory.join("sub")).unwrap();
+    fs::write(directory.join("intro.md"), "intro").unwrap();
+    let root = directory.to_str().unwrap();
+    let sub = directory.join("sub");
+    let configurations = [
+        planned(
+            root,
+            &[("a.md", &["missing.md"]), ("README.md", &["intro.md"])],
+        ),
+        planned(
+            sub.to_str().unwrap(),
+            &[
+                ("../README.md", &["../intro.md"]),
+                ("../b.md", &["../a.md"]),
+                ("../c.md", &["../README.md"]),
+                ("../d.md", &["../intro.md"]),
+            ],
+        ),
+    ];
+    let mut failures = Failures::new(true);
+    generate_planned(
+        &configurations,
+        &GenerateOptions::default(),
+        &mut Cache::default(),
+        &mut failures,
+    )
+    .unwrap();
+
+    let failed = failures
+        .failures
+        .iter()
+        .map(|failure| {
+            let output = failure.output.as_deref().unwrap();
+            let dependency = match &failure.error {
+                Error::DependencyFailed(dependency) => Some(dependency.as_path()),
+                _ => None,
+            };
+            (output, dependency)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        failed,
+        [
+            (directory.join("README.md").as_path(), None),
+            (directory.join("a.md").as_path(), None),
+            (
+                directory.join("b.md").as_path(),
+                Some(directory.join("a.md").as_path())
+            ),
+            (
+                directory.join("c.md").as_path(),
+                Some(directory.join("README.md").as_path())
+            ),
+        ]
+    );
+    assert!(!directory.join("b.md").exists());
+    assert!(!directory.join("c.md").exists());
+    assert_eq!(fs::read_to_string(directory.join("d.md")).unwrap(), "intro");
+}
+
+/// Makes `path` absolute and removes `.` and `..` components without
+/// accessing the filesystem, allowing paths written relative to different
+/// configurations to be compared.
+fn normalize_path(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
 /// A cache for loading snippets and glossaries.
 #[derive(Default)]
-pub struct Cache(HashMap<CacheKey, String>);
+pub struct Cache(HashMap<CacheKey, Resource>);
 
 #[derive(Hash, Eq, PartialEq)]
 enum CacheKey {
@@ -214,6 +1044,13 @@ enum CacheKey {
     Url(String),
 }
 
+#[derive(Clone)]
+struct Resource {
+    contents: String,
+    /// The `Content-Type` reported by the server, if loaded from a URL.
+    content_type: Option<String>,
+}
+
 impl Cache {
     fn get(
         &mut self,
@@ -221,17 +1058,43 @@ impl Cache {
         relative_to: &Path,
         not_found: impl FnOnce() -> Error,
     ) -> Result<String, Error> {
-        let cache_key = if resource.starts_with("http://") || resource.starts_with("https://") {
+        self.get_resource(resource, relative_to, not_found)
+            .map(|resource| resource.contents)
+    }
+
+    /// Replaces the cached contents of `path` with `contents` that were just
+    /// generated, ensuring later files that use `path` do not read stale
+    /// contents.
+    fn insert_generated(&mut self, path: &Path, contents: String) {
+        self.0.insert(
+            CacheKey::Path(normalize_path(path)),
+            Resource {
+                contents,
+                content_type: None,
+            },
+        );
+    }
+
+    fn get_resource(
+        &mut self,
+        resource: &str,
+        relative_to: &Path,
+        not_found: impl FnOnce() -> Error,
+    ) -> Result<Resource, Error> {
+        let cache_key = if is_url(resource) {
             CacheKey::Url(resource.to_string())
         } else {
-            CacheKey::Path(relative_to.join(resource))
+            CacheKey::Path(normalize_path(&relative_to.join(resource)))
         };
         if let Some(existing_value) = self.0.get(&cache_key) {
             Ok(existing_value.clone())
         } else {
-            let contents = match &cache_key {
-                CacheKey::Path(resource_path) => match std::fs::read_to_string(&resource_path) {
-                    Ok(contents) => contents,
+            let resource = match &cache_key {
+                CacheKey::Path(resource_path) => match std::fs::read_to_string(resource_path) {
+                    Ok(contents) => Resource {
+                        contents,
+                        content_type: None,
+                    },
                     Err(err) => {
                         if err.kind() == ErrorKind::NotFound {
                             return Err(not_found());
@@ -240,112 +1103,1415 @@ impl Cache {
                     }
                 },
                 CacheKey::Url(url) => {
-                    println!("Requesting {}", url);
+                    println!("Requesting {url}");
                     match ureq::get(url).set("User-Agent", "RustMe").call() {
-                        Ok(response) => response.into_string()?,
-                        Err(ureq::Error::Status(code, _)) if code == 404 => return Err(not_found()),
+                        Ok(response) => {
+                            let content_type = Some(response.content_type().to_string());
+                            Resource {
+                                contents: response.into_string()?,
+                                content_type,
+                            }
+                        }
+                        Err(ureq::Error::Status(404, _)) => return Err(not_found()),
                         Err(err) => return Err(Error::from(err)),
                     }
                 }
             };
-            self.0.insert(cache_key, contents.clone());
-            Ok(contents)
+            self.0.insert(cache_key, resource.clone());
+            Ok(resource)
+        }
+    }
+}
+
+/// The formats an external [`Glossary`] can be written in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum GlossaryFormat {
+    Ron,
+    Toml,
+    Json,
+}
+
+impl GlossaryFormat {
+    /// Detects the format of the glossary at `location`. A recognized file
+    /// extension takes precedence over `content_type`. If neither identify
+    /// the format, Ron is assumed.
+    fn detect(location: &str, content_type: Option<&str>) -> Self {
+        let path = location
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(location)
+            .to_ascii_lowercase();
+        let extension = Path::new(&path).extension().and_then(|ext| ext.to_str());
+        match extension {
+            Some("ron") => return Self::Ron,
+            Some("toml") => return Self::Toml,
+            Some("json") => return Self::Json,
+            _ => {}
+        }
+
+        let mime = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|mime| mime.trim().to_ascii_lowercase());
+        match mime.as_deref() {
+            Some(mime) if mime == "application/json" || mime.ends_with("+json") => Self::Json,
+            Some("application/toml" | "text/toml" | "text/x-toml") => Self::Toml,
+            _ => Self::Ron,
+        }
+    }
+
+    fn parse(self, contents: &str) -> Result<BTreeMap<String, Term>, Error> {
+        match self {
+            Self::Ron => Ok(ron::from_str(contents)?),
+            Self::Toml => Ok(toml::from_str(contents)?),
+            Self::Json => Ok(serde_json::from_str(contents)?),
+        }
+    }
+}
+
+#[test]
+fn glossary_format_tests() {
+    assert_eq!(
+        GlossaryFormat::detect("glossary.ron", None),
+        GlossaryFormat::Ron
+    );
+    assert_eq!(
+        GlossaryFormat::detect("glossary.toml", None),
+        GlossaryFormat::Toml
+    );
+    assert_eq!(
+        GlossaryFormat::detect("https://example.com/glossary.JSON?raw=true", None),
+        GlossaryFormat::Json
+    );
+    assert_eq!(
+        GlossaryFormat::detect(
+            "https://example.com/glossary",
+            Some("application/json; charset=utf-8")
+        ),
+        GlossaryFormat::Json
+    );
+    assert_eq!(
+        GlossaryFormat::detect("glossary.toml", Some("text/plain")),
+        GlossaryFormat::Toml
+    );
+    assert_eq!(
+        GlossaryFormat::detect("glossary", None),
+        GlossaryFormat::Ron
+    );
+
+    let expected = GlossaryFormat::Ron
+        .parse(r#"{"A": "a", "B": (default: "b", release: "r")}"#)
+        .unwrap();
+    let toml = GlossaryFormat::Toml
+        .parse("A = \"a\"\n\n[B]\ndefault = \"b\"\nrelease = \"r\"\n")
+        .unwrap();
+    let json = GlossaryFormat::Json
+        .parse(r#"{"A": "a", "B": {"default": "b", "release": "r"}}"#)
+        .unwrap();
+    assert_eq!(format!("{expected:?}"), format!("{toml:?}"));
+    assert_eq!(format!("{expected:?}"), format!("{json:?}"));
+}
+
+/// Options that control how files are generated.
+#[derive(Debug, Default, Clone)]
+pub struct GenerateOptions {
+    /// The profiles that are active for all files, in order of precedence.
+    /// Each [`File`] can activate additional profiles using
+    /// [`File::profiles`].
+    ///
+    /// Two profiles have special meaning: `release` is activated by
+    /// [`GenerateOptions::with_release`], and `for_docs` is activated for
+    /// files with [`File::for_docs`] set.
+    pub profiles: Vec<String>,
+    /// Glossary terms that take precedence over all terms defined in
+    /// [`Glossary`]s.
+    pub definitions: BTreeMap<String, String>,
+    /// Options controlling how [`generate_in_directory_with`] finds
+    /// configurations.
+    pub discovery: DiscoveryOptions,
+    /// If true, generation continues after an error, and all errors are
+    /// returned together as [`Error::Multiple`].
+    pub keep_going: bool,
+}
+
+impl GenerateOptions {
+    /// Adds `profile` to the list of active profiles and returns self.
+    #[must_use]
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Self {
+        self.profiles.push(profile.into());
+        self
+    }
+
+    /// Activates the `release` profile if `release` is true, and returns self.
+    #[must_use]
+    pub fn with_release(self, release: bool) -> Self {
+        if release {
+            self.with_profile("release")
+        } else {
+            self
+        }
+    }
+
+    /// Defines a glossary term named `name` as `value` and returns self. The
+    /// definition takes precedence over all terms defined in [`Glossary`]s.
+    #[must_use]
+    pub fn with_definition(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.definitions.insert(name.into(), value.into());
+        self
+    }
+
+    /// Replaces the options used to discover configurations and returns self.
+    #[must_use]
+    pub fn with_discovery(mut self, discovery: DiscoveryOptions) -> Self {
+        self.discovery = discovery;
+        self
+    }
+
+    /// Sets whether generation continues after an error and returns self.
+    #[must_use]
+    pub const fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    fn apply_definitions(&self, glossary: &mut HashMap<String, Term>) {
+        for (name, value) in &self.definitions {
+            glossary.insert(name.clone(), Term::Static(value.clone()));
+        }
+    }
+
+    /// Returns the profiles active for `file`, in order of precedence.
+    fn profiles_for(&self, file: &File) -> Vec<String> {
+        let mut profiles = Vec::new();
+        if file.for_docs {
+            profiles.push(String::from("for_docs"));
+        }
+        profiles.extend(file.profiles.iter().cloned());
+        profiles.extend(self.profiles.iter().cloned());
+        profiles
+    }
+}
+
+/// Options that control how configurations are found by
+/// [`discover_configurations`].
+///
+/// Directories named `target`, `.git`, and `node_modules` are never searched.
+/// Globs are matched against paths relative to the directory being searched.
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// If not empty, only configuration files matching at least one of these
+    /// globs are used, e.g. `crates/*/.rustme/config.ron`.
+    pub include: Vec<String>,
+    /// Files and directories matching any of these globs are skipped, e.g.
+    /// `vendor/**`.
+    pub exclude: Vec<String>,
+    /// The maximum depth to search. A depth of 1 only finds configurations
+    /// located directly within the directory being searched.
+    pub max_depth: Option<usize>,
+    /// If true, paths ignored by `.gitignore` and `.ignore` files are skipped.
+    /// Defaults to true.
+    pub respect_ignore_files: bool,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+            respect_ignore_files: true,
         }
     }
 }
 
+impl DiscoveryOptions {
+    /// Adds `glob` to the list of included configurations and returns self.
+    #[must_use]
+    pub fn with_include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Adds `glob` to the list of excluded paths and returns self.
+    #[must_use]
+    pub fn with_exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// Sets the maximum depth to search and returns self.
+    #[must_use]
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets whether `.gitignore` and `.ignore` files are respected and returns
+    /// self.
+    #[must_use]
+    pub const fn with_ignore_files(mut self, respect_ignore_files: bool) -> Self {
+        self.respect_ignore_files = respect_ignore_files;
+        self
+    }
+}
+
+/// Directories that never contain configurations that should be generated.
+const SKIPPED_DIRECTORIES: [&str; 3] = ["target", ".git", "node_modules"];
+
+fn glob_set(globs: &[String]) -> Result<GlobSet, Error> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        set.add(
+            GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| Error::InvalidGlob(err.to_string()))?,
+        );
+    }
+    set.build()
+        .map_err(|err| Error::InvalidGlob(err.to_string()))
+}
+
 #[derive(Copy, Clone)]
-struct Context {
-    for_docs: bool,
-    release: bool,
+struct Context<'a> {
+    profiles: &'a [String],
+    file: &'a File,
+    delimiters: &'a ReferenceDelimiters,
+    package: Option<&'a Package>,
+}
+
+impl Context<'_> {
+    /// Returns true if `condition` names an active profile. A leading `!`
+    /// negates the condition.
+    fn is_profile_active(&self, condition: &str) -> bool {
+        if let Some(condition) = condition.strip_prefix('!') {
+            return !self.is_profile_active(condition.trim_start());
+        }
+
+        self.profiles.iter().any(|profile| profile == condition)
+    }
+}
+
+/// The delimiters that surround a reference to a snippet or glossary term.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceDelimiters {
+    /// The text that begins a reference. Must not be empty.
+    pub start: String,
+    /// The text that ends a reference. Must not be empty.
+    pub end: String,
 }
 
+impl Default for ReferenceDelimiters {
+    fn default() -> Self {
+        Self {
+            start: String::from("$"),
+            end: String::from("$"),
+        }
+    }
+}
+
+impl ReferenceDelimiters {
+    const fn validate(&self) -> Result<(), Error> {
+        if self.start.is_empty() || self.end.is_empty() {
+            Err(Error::EmptyReferenceDelimiter)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The prefix that marks a reference as explicit, allowing it to be expanded
+/// within code when [`File::verbatim_code`] is enabled.
+const EXPLICIT_REFERENCE_PREFIX: &str = "rustme:";
+
 fn replace_references(
     markdown: &str,
     base_dir: &Path,
     snippets: &mut HashMap<String, String>,
     glossary: &HashMap<String, Term>,
-    context: Context,
+    context: Context<'_>,
 ) -> Result<String, Error> {
-    let mut processed = Vec::with_capacity(markdown.len());
-    let mut chars = StrByteIterator::new(markdown);
-    loop {
-        let skipped = chars.read_until_char(b'$')?;
-        if !skipped.is_empty() {
-            processed.extend(skipped.bytes());
+    let code_ranges = if context.file.verbatim_code || context.file.fence_snippets {
+        code_ranges(markdown)
+    } else {
+        Vec::new()
+    };
+    let ReferenceDelimiters {
+        start: start_delimiter,
+        end: end_delimiter,
+    } = context.delimiters;
+    let mut processed = String::with_capacity(markdown.len());
+    let mut offset = 0;
+    while let Some(start) = markdown[offset..].find(start_delimiter.as_str()) {
+        let start = offset + start;
+        processed.push_str(&markdown[offset..start]);
+        offset = start + start_delimiter.len();
+
+        if context.file.verbatim_code && is_verbatim(markdown, start, offset, &code_ranges) {
+            // Code is left verbatim, including any escaped delimiters.
+            processed.push_str(start_delimiter);
+            continue;
         }
-        // Skip the $, or exit if one wasn't found.
-        if chars.next().is_none() {
-            break;
+
+        let end = markdown[offset..]
+            .find(end_delimiter.as_str())
+            .ok_or_else(|| Error::at(markdown, start, Error::MalformedSnippetReference))?
+            + offset;
+        let snippet_ref = markdown[offset..end].trim();
+        // Skip the end delimiter
+        offset = end + end_delimiter.len();
+        if snippet_ref.is_empty() {
+            // An escaped delimiter, such as `$$`.
+            processed.push_str(start_delimiter);
+            continue;
+        }
+        let snippet_ref = snippet_ref
+            .strip_prefix(EXPLICIT_REFERENCE_PREFIX)
+            .unwrap_or(snippet_ref);
+
+        let mut parts = snippet_ref.split('|');
+        let name = parts.next().unwrap_or_default();
+        let mut filters = parts
+            .map(SnippetFilter::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| Error::at(markdown, start, err))?;
+        let (value, language) = if let Some(term) = glossary.get(name) {
+            (term.to_string(context), language_for_snippet(name))
+        } else {
+            let (value, language) =
+                if let Some(builtin) = name.strip_prefix(BUILTIN_REFERENCE_PREFIX) {
+                    expand_builtin(builtin, context)
+                } else {
+                    load_snippet(name, base_dir, snippets)
+                        .map(|snippet| (snippet.clone(), language_for_snippet(name)))
+                }
+                .map_err(|err| Error::at(markdown, start, err))?;
+            if context.file.fence_snippets
+                && language.is_some()
+                && !filters
+                    .iter()
+                    .any(|filter| matches!(filter, SnippetFilter::Fence(_)))
+                && is_alone_on_line(markdown, start..offset)
+                && !code_ranges.iter().any(|range| range.contains(&start))
+            {
+                filters.push(SnippetFilter::Fence(None));
+            }
+            (value, language)
+        };
+        processed.push_str(&apply_filters(value, language, &filters));
+    }
+    processed.push_str(&markdown[offset..]);
+    Ok(processed)
+}
+
+/// Returns true if the reference beginning at `start`, whose contents begin at
+/// `reference_start`, is inside of code and is not explicit.
+fn is_verbatim(
+    markdown: &str,
+    start: usize,
+    reference_start: usize,
+    code_ranges: &[Range<usize>],
+) -> bool {
+    code_ranges.iter().any(|range| range.contains(&start))
+        && !markdown[reference_start..]
+            .trim_start()
+            .starts_with(EXPLICIT_REFERENCE_PREFIX)
+}
+
+/// Returns the byte ranges of all code blocks and inline code in `markdown`.
+fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(markdown, rustdoc_markdown_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn verbatim_code_tests() {
+    let mut snippets = HashMap::new();
+    snippets.insert(String::from("main.rs:example"), String::from("let a = 1;"));
+    let mut glossary = HashMap::new();
+    glossary.insert(String::from("HOME"), Term::Static(String::from("home")));
+    let file = File {
+        verbatim_code: true,
+        ..File::default()
+    };
+    let context = Context {
+        profiles: &[],
+        file: &file,
+        delimiters: &ReferenceDelimiters::default(),
+        package: None,
+    };
+    let markdown = "Visit $HOME$, or run `cd $HOME`.\n\n```sh\necho $HOME $PATH $$\n```\n\n```rust\n$rustme:main.rs:example$\n```\n";
+    assert_eq!(
+        replace_references(markdown, Path::new("."), &mut snippets, &glossary, context).unwrap(),
+        "Visit home, or run `cd $HOME`.\n\n```sh\necho $HOME $PATH $$\n```\n\n```rust\nlet a = 1;\n```\n"
+    );
+}
+
+/// A transformation applied to a snippet using the `$name|filter$` syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SnippetFilter {
+    /// `trim`: removes blank lines from the start and end of the snippet.
+    Trim,
+    /// `hide=3..5`: prefixes the listed lines with `# ` so that rustdoc
+    /// hides them. Lines are numbered starting at 1.
+    Hide(Vec<(usize, usize)>),
+    /// `fence=rust`: wraps the snippet in a fenced code block. If no language
+    /// is specified, it is inferred from the snippet's file extension, or
+    /// provided by the built-in reference.
+    Fence(Option<String>),
+    /// `indent=4`: indents each non-empty line by the number of spaces.
+    Indent(usize),
+}
+
+impl SnippetFilter {
+    fn parse(filter: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidSnippetFilter(filter.to_string());
+        let (name, value) = match filter.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (filter.trim(), None),
+        };
+        match (name, value) {
+            ("trim", None) => Ok(Self::Trim),
+            ("hide", Some(lines)) => lines
+                .split(',')
+                .map(|range| parse_line_range(range).ok_or_else(invalid))
+                .collect::<Result<_, _>>()
+                .map(Self::Hide),
+            ("fence", value) => Ok(Self::Fence(value.map(ToString::to_string))),
+            ("indent", Some(spaces)) => spaces.parse().map(Self::Indent).map_err(|_| invalid()),
+            _ => Err(invalid()),
         }
+    }
 
-        let snippet_ref = chars.read_until_char(b'$')?;
-        // Skip the trailing $
-        if chars.next().is_none() {
-            return Err(Error::MalformedCodeBlock);
+    /// Filters are applied in this order regardless of the order they were
+    /// written, allowing `hide` line numbers to always refer to the original
+    /// snippet and `indent` to include the fence.
+    const fn order(&self) -> u8 {
+        match self {
+            Self::Hide(_) => 0,
+            Self::Trim => 1,
+            Self::Fence(_) => 2,
+            Self::Indent(_) => 3,
         }
-        if snippet_ref.is_empty() {
-            // An escaped dollar sign
-            processed.push(b'$');
-        } else if let Some(term) = glossary.get(snippet_ref) {
-            processed.extend(term.to_string(context).bytes());
+    }
+
+    fn apply(&self, snippet: &str, default_language: Option<&str>) -> String {
+        match self {
+            Self::Trim => {
+                let lines = snippet.lines().collect::<Vec<_>>();
+                let start = lines
+                    .iter()
+                    .position(|line| !line.trim().is_empty())
+                    .unwrap_or(lines.len());
+                let end = lines
+                    .iter()
+                    .rposition(|line| !line.trim().is_empty())
+                    .map_or(start, |index| index + 1);
+                lines[start..end].join("\n")
+            }
+            Self::Hide(ranges) => snippet
+                .lines()
+                .enumerate()
+                .map(|(index, line)| {
+                    let line_number = index + 1;
+                    if ranges
+                        .iter()
+                        .any(|(start, end)| (*start..*end).contains(&line_number))
+                    {
+                        format!("# {line}")
+                    } else {
+                        line.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Fence(language) => {
+                let language = language.as_deref().or(default_language).unwrap_or_default();
+                let fence = code_fence_for(snippet);
+                format!("{fence}{language}\n{snippet}\n{fence}")
+            }
+            Self::Indent(spaces) => {
+                let indentation = " ".repeat(*spaces);
+                snippet
+                    .lines()
+                    .map(|line| {
+                        if line.is_empty() {
+                            String::new()
+                        } else {
+                            format!("{indentation}{line}")
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+    }
+}
+
+/// Applies `filters` to `snippet`. `language` is used by a `fence` filter
+/// that does not specify a language. The trailing newline of a snippet that
+/// refers to an entire file is removed first, so filtered snippets never end
+/// with a newline.
+fn apply_filters(mut snippet: String, language: Option<&str>, filters: &[SnippetFilter]) -> String {
+    if filters.is_empty() {
+        return snippet;
+    }
+    if snippet.ends_with('\n') {
+        snippet.pop();
+        if snippet.ends_with('\r') {
+            snippet.pop();
+        }
+    }
+    let mut filters = filters.iter().collect::<Vec<_>>();
+    filters.sort_by_key(|filter| filter.order());
+    for filter in filters {
+        snippet = filter.apply(&snippet, language);
+    }
+    snippet
+}
+
+#[test]
+fn reference_delimiters_tests() {
+    let mut glossary = HashMap::new();
+    glossary.insert(String::from("NAME"), Term::Static(String::from("rustme")));
+    let file = File::default();
+    let delimiters = ReferenceDelimiters {
+        start: String::from("{{"),
+        end: String::from("}}"),
+    };
+    let context = Context {
+        profiles: &[],
+        file: &file,
+        delimiters: &delimiters,
+        package: None,
+    };
+    assert_eq!(
+        replace_references(
+            "$ cargo install {{ NAME }} # costs $0, {{}}",
+            Path::new("."),
+            &mut HashMap::new(),
+            &glossary,
+            context
+        )
+        .unwrap(),
+        "$ cargo install rustme # costs $0, {{"
+    );
+    assert!(matches!(
+        replace_references(
+            "{{ NAME",
+            Path::new("."),
+            &mut HashMap::new(),
+            &glossary,
+            context
+        ),
+        Err(Error::Markdown { line: 1, column: 1, error }) if matches!(*error, Error::MalformedSnippetReference)
+    ));
+}
+
+#[test]
+fn empty_reference_delimiters_tests() {
+    let mut planned = planned(".", &[("README.md", &["README-source.md"])]);
+    planned.configuration.reference_delimiters.end = String::new();
+    assert!(matches!(
+        planned.configuration.generate_file(
+            &planned.files[0],
+            &GenerateOptions::default(),
+            &mut Cache::default(),
+            &mut HashMap::new(),
+        ),
+        Err(Error::EmptyReferenceDelimiter)
+    ));
+}
+
+#[test]
+fn fence_snippets_tests() {
+    let mut snippets = HashMap::new();
+    snippets.insert(String::from("main.rs:example"), String::from("let a = 1;"));
+    let file = File {
+        fence_snippets: true,
+        ..File::default()
+    };
+    let context = Context {
+        profiles: &[],
+        file: &file,
+        delimiters: &ReferenceDelimiters::default(),
+        package: None,
+    };
+    let markdown = "$main.rs:example$\n\n```rust\n$main.rs:example$\n```\n\n$main.rs:example$ inline\n\n    $main.rs:example$\n";
+    assert_eq!(
+        replace_references(
+            markdown,
+            Path::new("."),
+            &mut snippets,
+            &HashMap::new(),
+            context
+        )
+        .unwrap(),
+        "```rust\nlet a = 1;\n```\n\n```rust\nlet a = 1;\n```\n\nlet a = 1; inline\n\n    let a = 1;\n"
+    );
+}
+
+/// The prefix of references that are generated by `rustme` from the Cargo
+/// package containing the configuration.
+const BUILTIN_REFERENCE_PREFIX: &str = "cargo:";
+
+/// Expands a built-in reference, such as `cargo:dependency precision=major`.
+/// The reference's name is followed by whitespace-separated `key=value`
+/// arguments. Returns the expanded text and the language used to fence it.
+fn expand_builtin(
+    reference: &str,
+    context: Context<'_>,
+) -> Result<(String, Option<&'static str>), Error> {
+    let mut parts = reference.split_whitespace();
+    let name = parts.next().unwrap_or_default();
+    let arguments = parts
+        .map(|argument| {
+            argument
+                .split_once('=')
+                .ok_or_else(|| Error::InvalidBuiltinReference(reference.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let package = context
+        .package
+        .ok_or_else(|| Error::PackageNotFound(reference.to_string()))?;
+    match name {
+        "dependency" => {
+            let mut precision = VersionPrecision::Minor;
+            let mut features = Vec::new();
+            let mut default_features = true;
+            for (key, value) in arguments {
+                match (key, value) {
+                    ("precision", value) => {
+                        precision = VersionPrecision::parse(value)
+                            .ok_or_else(|| Error::InvalidBuiltinReference(reference.to_string()))?;
+                    }
+                    ("features", value) => {
+                        features.extend(
+                            value
+                                .split(',')
+                                .filter(|feature| !feature.is_empty())
+                                .map(ToString::to_string),
+                        );
+                    }
+                    ("default-features", "true") => default_features = true,
+                    ("default-features", "false") => default_features = false,
+                    _ => return Err(Error::InvalidBuiltinReference(reference.to_string())),
+                }
+            }
+            Ok((
+                package.dependency_snippet(precision, &features, default_features),
+                Some("toml"),
+            ))
+        }
+        "features" if arguments.is_empty() => Ok((package.features_table(), None)),
+        _ => Err(Error::InvalidBuiltinReference(reference.to_string())),
+    }
+}
+
+#[test]
+fn builtin_dependency_tests() {
+    let package = Package {
+        manifest: crate::manifest::Manifest::parse(String::from(
+            r#"
+            [package]
+            name = "rustme"
+            version = "0.1.1"
+            "#,
+        ))
+        .unwrap(),
+        workspace: None,
+    };
+    let file = File::default();
+    let delimiters = ReferenceDelimiters::default();
+    let context = Context {
+        profiles: &[],
+        file: &file,
+        delimiters: &delimiters,
+        package: Some(&package),
+    };
+    let process = |markdown: &str| {
+        replace_references(
+            markdown,
+            Path::new("."),
+            &mut HashMap::new(),
+            &HashMap::new(),
+            context,
+        )
+    };
+    assert_eq!(
+        process("$cargo:dependency|fence$").unwrap(),
+        "```toml\n[dependencies]\nrustme = \"0.1\"\n```"
+    );
+    assert_eq!(
+        process("$cargo:dependency precision=patch features=a,b default-features=false$").unwrap(),
+        "[dependencies]\nrustme = { version = \"0.1.1\", default-features = false, features = [\"a\", \"b\"] }"
+    );
+    assert!(matches!(
+        process("$cargo:dependency precision=nano$"),
+        Err(Error::Markdown { error, .. }) if matches!(*error, Error::InvalidBuiltinReference(_))
+    ));
+    assert!(matches!(
+        process("$cargo:unknown$"),
+        Err(Error::Markdown { error, .. }) if matches!(*error, Error::InvalidBuiltinReference(_))
+    ));
+}
+
+/// Returns the code block language for the file a snippet is loaded from.
+fn language_for_snippet(name: &str) -> Option<&'static str> {
+    let path = name.split(':').next().unwrap_or_default();
+    let extension = Path::new(path).extension()?.to_str()?;
+    Some(match extension.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "ron" => "ron",
+        "sh" | "bash" => "sh",
+        "ps1" => "powershell",
+        "json" => "json",
+        "yml" | "yaml" => "yaml",
+        "md" => "markdown",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "py" => "python",
+        "c" | "h" => "c",
+        "cpp" | "hpp" | "cc" => "cpp",
+        "sql" => "sql",
+        "wgsl" => "wgsl",
+        _ => return None,
+    })
+}
+
+/// Returns true if only whitespace surrounds `range` on its line.
+fn is_alone_on_line(markdown: &str, range: Range<usize>) -> bool {
+    let before = markdown[..range.start]
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default();
+    let after = markdown[range.end..].split('\n').next().unwrap_or_default();
+    before.trim().is_empty() && after.trim().is_empty()
+}
+
+/// Parses `3`, `3..5`, or `3..=5` into a half-open range of line numbers.
+fn parse_line_range(range: &str) -> Option<(usize, usize)> {
+    let range = range.trim();
+    if let Some((start, end)) = range.split_once("..=") {
+        Some((start.parse().ok()?, end.parse::<usize>().ok()? + 1))
+    } else if let Some((start, end)) = range.split_once("..") {
+        Some((start.parse().ok()?, end.parse().ok()?))
+    } else {
+        let line = range.parse::<usize>().ok()?;
+        Some((line, line + 1))
+    }
+}
+
+/// Returns a backtick fence that is longer than any run of backticks inside
+/// of `snippet`.
+fn code_fence_for(snippet: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for ch in snippet.chars() {
+        if ch == '`' {
+            current += 1;
+            longest = longest.max(current);
         } else {
-            let snippet = load_snippet(snippet_ref, base_dir, snippets)?;
-            processed.extend(snippet.bytes());
+            current = 0;
         }
     }
-    Ok(String::from_utf8(processed)?)
+    "`".repeat((longest + 1).max(3))
 }
 
-fn preprocess_rust_codeblocks(markdown: &str) -> Result<String, Error> {
-    let mut processed = Vec::with_capacity(markdown.len());
-    let mut chars = StrByteIterator::new(markdown);
-    while let Some(ch) = chars.next() {
-        match ch {
-            b'`' => {
-                if chars.try_read("``rust") {
-                    // Preprocess rust blocks in the same way that rustdoc does.
-                    processed.extend(b"```rust");
-                    let rest_of_line = chars.read_line()?;
-                    processed.extend(rest_of_line.bytes());
+#[test]
+fn snippet_filter_tests() {
+    let snippet = String::from("\nfn main() {\n    let a = 1;\n}\n");
+    let filters = "indent=2|fence=rust|trim|hide=2"
+        .split('|')
+        .map(SnippetFilter::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        apply_filters(snippet, language_for_snippet("main.rs"), &filters),
+        "  ```rust\n  # fn main() {\n      let a = 1;\n  }\n  ```"
+    );
 
-                    loop {
-                        let line = chars.read_line()?;
-                        if line.is_empty() {
-                            return Err(Error::MalformedCodeBlock);
+    assert_eq!(
+        apply_filters(
+            String::from("```\ncode\n```"),
+            language_for_snippet("README.md"),
+            &[SnippetFilter::Fence(None)]
+        ),
+        "````markdown\n```\ncode\n```\n````"
+    );
+
+    assert_eq!(
+        apply_filters(
+            String::from("[a]"),
+            language_for_snippet("config.ron"),
+            &[SnippetFilter::Fence(None)]
+        ),
+        "```ron\n[a]\n```"
+    );
+
+    // Snippets referring to an entire file end with a newline.
+    assert_eq!(
+        apply_filters(
+            String::from("fn main() {}\n"),
+            language_for_snippet("main.rs"),
+            &[SnippetFilter::Fence(None)]
+        ),
+        "```rust\nfn main() {}\n```"
+    );
+    assert_eq!(
+        apply_filters(
+            String::from("fn main() {}\n"),
+            None,
+            &[SnippetFilter::Indent(4), SnippetFilter::Hide(vec![(1, 2)])]
+        ),
+        "    # fn main() {}"
+    );
+
+    assert!(matches!(
+        SnippetFilter::parse("hide=a..b"),
+        Err(Error::InvalidSnippetFilter(_))
+    ));
+    assert!(matches!(
+        SnippetFilter::parse("unknown"),
+        Err(Error::InvalidSnippetFilter(_))
+    ));
+}
+
+/// Removes the lines that rustdoc hides from Rust code blocks, making the
+/// blocks render the same as they do in rustdoc.
+fn preprocess_rust_codeblocks(markdown: &str) -> String {
+    let mut processed = String::with_capacity(markdown.len());
+    let mut copied_until = 0;
+    let mut in_rust_block = false;
+    for (event, range) in Parser::new_ext(markdown, rustdoc_markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => in_rust_block = is_rust_code_block(&kind),
+            Event::End(TagEnd::CodeBlock) => in_rust_block = false,
+            Event::Text(_) if in_rust_block => {
+                // Code blocks nested in containers, such as lists or block
+                // quotes, have each line emitted separately, with `range`
+                // excluding the container's prefix.
+                let mut line_offset = range.start;
+                for line in markdown[range].split_inclusive('\n') {
+                    match map_rustdoc_line(line) {
+                        RustdocLine::Hidden => {
+                            let line_start = markdown[..line_offset]
+                                .rfind('\n')
+                                .map_or(0, |index| index + 1)
+                                .max(copied_until);
+                            processed.push_str(&markdown[copied_until..line_start]);
+                            copied_until = line_offset + line.len();
                         }
-                        let trimmed_start = line.trim_start();
-                        if trimmed_start.starts_with("```") {
-                            // Ends the code block
-                            processed.extend(line.bytes());
-                            break;
-                        } else if trimmed_start.starts_with("# ") {
-                            // A rust-doc comment
-                        } else {
-                            processed.extend(line.bytes());
+                        RustdocLine::Shown(Cow::Owned(replacement)) => {
+                            processed.push_str(&markdown[copied_until..line_offset]);
+                            processed.push_str(&replacement);
+                            copied_until = line_offset + line.len();
                         }
+                        RustdocLine::Shown(Cow::Borrowed(_)) => {}
                     }
-                } else {
-                    processed.push(ch);
+                    line_offset += line.len();
                 }
             }
-            ch => {
-                processed.push(ch);
+            _ => {}
+        }
+    }
+    processed.push_str(&markdown[copied_until..]);
+    processed
+}
+
+/// The markdown extensions that rustdoc enables.
+fn rustdoc_markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_SMART_PUNCTUATION
+}
+
+/// Returns true if rustdoc treats the code block as Rust code. Indented and
+/// unlabeled code blocks are Rust, as are blocks whose info string contains
+/// only `rust` and doctest attributes.
+fn is_rust_code_block(kind: &CodeBlockKind<'_>) -> bool {
+    let info = match kind {
+        CodeBlockKind::Indented => return true,
+        CodeBlockKind::Fenced(info) => info,
+    };
+    let mut seen_rust_tags = false;
+    let mut seen_other_tags = false;
+    for token in info
+        .split([',', ' ', '\t'])
+        .filter(|token| !token.is_empty())
+    {
+        match token {
+            "rust" | "should_panic" | "no_run" | "ignore" | "test_harness" | "compile_fail"
+            | "standalone_crate" => seen_rust_tags = true,
+            token
+                if token.starts_with("ignore-")
+                    || token.starts_with("edition")
+                    || is_error_code(token) =>
+            {
+                seen_rust_tags = true;
             }
+            _ => seen_other_tags = true,
         }
     }
-    Ok(String::from_utf8(processed)?)
+    seen_rust_tags || !seen_other_tags
+}
+
+/// Returns true if `token` is an error code, such as `E0123`.
+fn is_error_code(token: &str) -> bool {
+    token.len() == 5
+        && token.starts_with('E')
+        && token[1..].bytes().all(|byte| byte.is_ascii_digit())
+}
+
+enum RustdocLine<'a> {
+    Hidden,
+    Shown(Cow<'a, str>),
+}
+
+/// Applies rustdoc's rules for hiding lines: a line is hidden if it begins
+/// with `# ` or is exactly `#`, and a leading `##` is escaped to become `#`.
+fn map_rustdoc_line(line: &str) -> RustdocLine<'_> {
+    let trimmed = line.trim();
+    if trimmed.starts_with("##") {
+        RustdocLine::Shown(Cow::Owned(line.replacen("##", "#", 1)))
+    } else if trimmed.starts_with("# ") || trimmed == "#" {
+        RustdocLine::Hidden
+    } else {
+        RustdocLine::Shown(Cow::Borrowed(line))
+    }
+}
+
+#[test]
+fn preprocess_rust_codeblocks_tests() {
+    // Fences using tildes or more than three backticks.
+    assert_eq!(
+        preprocess_rust_codeblocks("~~~rust\n# hidden\nshown\n~~~\n"),
+        "~~~rust\nshown\n~~~\n"
+    );
+    assert_eq!(
+        preprocess_rust_codeblocks("````rust\n```\n# hidden\n````\n"),
+        "````rust\n```\n````\n"
+    );
+    // Unlabeled fences and doctest attributes are Rust.
+    assert_eq!(
+        preprocess_rust_codeblocks("```\n# hidden\n```\n"),
+        "```\n```\n"
+    );
+    assert_eq!(
+        preprocess_rust_codeblocks("``` rust, ignore\n# hidden\n```\n"),
+        "``` rust, ignore\n```\n"
+    );
+    assert_eq!(
+        preprocess_rust_codeblocks("```no_run,edition2021\n# hidden\n```\n"),
+        "```no_run,edition2021\n```\n"
+    );
+    // Other languages are left alone.
+    let shell = "```sh\n# a comment\n```\n";
+    assert_eq!(preprocess_rust_codeblocks(shell), shell);
+    let text = "```text\n# hidden\n```\n";
+    assert_eq!(preprocess_rust_codeblocks(text), text);
+    // Fences nested inside of lists and block quotes.
+    assert_eq!(
+        preprocess_rust_codeblocks("- item\n\n  ```rust\n  # hidden\n  shown\n  ```\n"),
+        "- item\n\n  ```rust\n  shown\n  ```\n"
+    );
+    assert_eq!(
+        preprocess_rust_codeblocks("> ```rust\n> # hidden\n> shown\n> ```\n"),
+        "> ```rust\n> shown\n> ```\n"
+    );
+    // Indented code blocks are Rust.
+    assert_eq!(
+        preprocess_rust_codeblocks("text\n\n    # hidden\n    shown\n"),
+        "text\n\n    shown\n"
+    );
+    // A line starting with "#" outside of a code block is a heading.
+    assert_eq!(preprocess_rust_codeblocks("# Heading\n"), "# Heading\n");
+    // A bare `#` is hidden, and `##` escapes a literal `#`.
+    assert_eq!(
+        preprocess_rust_codeblocks("```rust\n#\n  #\n##[derive(Debug)]\n    ## not hidden\n```\n"),
+        "```rust\n#[derive(Debug)]\n    # not hidden\n```\n"
+    );
+    // Unclosed blocks continue until the end of the document.
+    assert_eq!(
+        preprocess_rust_codeblocks("```rust\nshown\n# hidden"),
+        "```rust\nshown\n"
+    );
+}
+
+#[test]
+fn preprocess_rust_codeblocks_corpus() {
+    // Doctests in the style of the standard library's documentation, paired
+    // with how rustdoc renders them.
+    const CORPUS: &[(&str, &str)] = &[
+        (
+            "```\n# #![allow(unused)]\n# fn main() {\nlet v = vec![1, 2, 3];\nassert_eq!(v.len(), 3);\n# }\n```\n",
+            "```\nlet v = vec![1, 2, 3];\nassert_eq!(v.len(), 3);\n```\n",
+        ),
+        (
+            "```\nuse std::fs::File;\nuse std::io::prelude::*;\n\nfn main() -> std::io::Result<()> {\n    let mut file = File::create(\"foo.txt\")?;\n    file.write_all(b\"Hello, world!\")?;\n#\n#   Ok(())\n}\n```\n",
+            "```\nuse std::fs::File;\nuse std::io::prelude::*;\n\nfn main() -> std::io::Result<()> {\n    let mut file = File::create(\"foo.txt\")?;\n    file.write_all(b\"Hello, world!\")?;\n}\n```\n",
+        ),
+        (
+            "```rust\nmacro_rules! example {\n    () => {\n        ##[derive(Debug)]\n        struct Example;\n    };\n}\n# example!();\n```\n",
+            "```rust\nmacro_rules! example {\n    () => {\n        #[derive(Debug)]\n        struct Example;\n    };\n}\n```\n",
+        ),
+        (
+            "```should_panic\n#[derive(Debug)]\nstruct Point;\n# fn check() -> bool { false }\nassert!(check());\n```\n",
+            "```should_panic\n#[derive(Debug)]\nstruct Point;\nassert!(check());\n```\n",
+        ),
+        (
+            "```toml\n# Cargo.toml comments are not hidden\n[dependencies]\n```\n",
+            "```toml\n# Cargo.toml comments are not hidden\n[dependencies]\n```\n",
+        ),
+    ];
+
+    for (markdown, expected) in CORPUS {
+        assert_eq!(&preprocess_rust_codeblocks(markdown), expected);
+    }
+}
+
+fn process_markdown(
+    markdown: &str,
+    base_dir: &Path,
+    snippets: &mut HashMap<String, String>,
+    glossary: &HashMap<String, Term>,
+    context: Context<'_>,
+) -> Result<String, Error> {
+    let (markdown, source_map) = evaluate_conditionals(markdown, context)?;
+    let expanded = replace_references(&markdown, base_dir, snippets, glossary, context)
+        .map_err(|err| source_map.relocate(&markdown, err))?;
+    if context.file.for_docs {
+        // Rustdoc needs the hidden lines to compile the doctests.
+        Ok(expanded)
+    } else {
+        Ok(preprocess_rust_codeblocks(&expanded))
+    }
+}
+
+/// Evaluates `$if flag$ ... $else$ ... $endif$` blocks, removing the text of
+/// all branches that are not taken. Conditions can be negated using `!flag`.
+/// Directives that are on a line by themselves remove the entire line.
+///
+/// When [`File::verbatim_code`] is enabled, directives inside of code are only
+/// evaluated if they use the `rustme:` prefix, e.g. `$rustme: if release$`.
+///
+/// All other references are copied as-is to be expanded by
+/// [`replace_references`]. The returned [`SourceMap`] maps positions in the
+/// result back to `markdown`.
+fn evaluate_conditionals<'a>(
+    markdown: &'a str,
+    context: Context<'_>,
+) -> Result<(String, SourceMap<'a>), Error> {
+    let ReferenceDelimiters {
+        start: start_delimiter,
+        end: end_delimiter,
+    } = context.delimiters;
+    let code_ranges = if context.file.verbatim_code {
+        code_ranges(markdown)
+    } else {
+        Vec::new()
+    };
+    let mut processed = String::with_capacity(markdown.len());
+    let mut source_map = SourceMap::new(markdown);
+    // Each entry is whether the current branch of an enclosing block is
+    // taken, whether an `else` has been encountered, and where the block
+    // started.
+    let mut blocks = Vec::<(bool, bool, usize)>::new();
+    let mut offset = 0;
+    while let Some(start) = markdown[offset..].find(start_delimiter.as_str()) {
+        let start = offset + start;
+        let active = blocks.iter().all(|(taken, ..)| *taken);
+        if active {
+            source_map.push(&mut processed, offset..start);
+        }
+        let reference_start = start + start_delimiter.len();
+        if is_verbatim(markdown, start, reference_start, &code_ranges) {
+            // Code is left verbatim and its delimiters are not paired.
+            if active {
+                source_map.push(&mut processed, start..reference_start);
+            }
+            offset = reference_start;
+            continue;
+        }
+        let Some(end) = markdown[reference_start..].find(end_delimiter.as_str()) else {
+            // Unterminated references are reported by replace_references.
+            offset = start;
+            break;
+        };
+        let end = reference_start + end;
+        let mut directive_end = end + end_delimiter.len();
+
+        let reference = markdown[reference_start..end].trim();
+        let reference = reference
+            .strip_prefix(EXPLICIT_REFERENCE_PREFIX)
+            .map_or(reference, str::trim_start);
+        let malformed = || Error::at(markdown, start, Error::MalformedConditional);
+        let directive = if let Some(condition) = reference.strip_prefix("if ") {
+            blocks.push((context.is_profile_active(condition.trim()), false, start));
+            true
+        } else if reference == "else" {
+            let (taken, has_else, _) = blocks.last_mut().ok_or_else(malformed)?;
+            if *has_else {
+                return Err(malformed());
+            }
+            *taken = !*taken;
+            *has_else = true;
+            true
+        } else if reference == "endif" {
+            blocks.pop().ok_or_else(malformed)?;
+            true
+        } else {
+            false
+        };
+
+        if directive {
+            let line_start = markdown[..start].rfind('\n').map_or(0, |index| index + 1);
+            let rest_of_line = markdown[directive_end..]
+                .find('\n')
+                .map_or(markdown.len(), |index| directive_end + index + 1);
+            if markdown[line_start..start].trim().is_empty()
+                && markdown[directive_end..rest_of_line].trim().is_empty()
+            {
+                if active {
+                    let length = processed.len() - (start - line_start.max(offset));
+                    source_map.truncate(&mut processed, length);
+                }
+                directive_end = rest_of_line;
+            }
+        } else if active {
+            source_map.push(&mut processed, start..directive_end);
+        }
+        offset = directive_end;
+    }
+
+    if let Some((_, _, start)) = blocks.last() {
+        return Err(Error::at(markdown, *start, Error::MalformedConditional));
+    }
+    source_map.push(&mut processed, offset..markdown.len());
+    Ok((processed, source_map))
+}
+
+/// Maps byte offsets in text produced by copying ranges of `source` back to
+/// offsets in `source`.
+struct SourceMap<'a> {
+    source: &'a str,
+    /// The offset in the produced text and in `source` where each copied
+    /// range starts.
+    segments: Vec<(usize, usize)>,
+}
+
+impl<'a> SourceMap<'a> {
+    const fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Appends `range` of the source to `processed`.
+    fn push(&mut self, processed: &mut String, range: Range<usize>) {
+        self.segments.push((processed.len(), range.start));
+        processed.push_str(&self.source[range]);
+    }
+
+    /// Truncates `processed` to `length` bytes.
+    fn truncate(&mut self, processed: &mut String, length: usize) {
+        processed.truncate(length);
+        self.segments.retain(|(start, _)| *start <= length);
+    }
+
+    fn source_offset(&self, offset: usize) -> usize {
+        self.segments
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= offset)
+            .map_or(offset, |(start, source)| source + offset - start)
+    }
+
+    /// Moves the position of an [`Error::Markdown`] in `processed` to the
+    /// corresponding position in the source.
+    fn relocate(&self, processed: &str, error: Error) -> Error {
+        match error {
+            Error::Markdown {
+                line,
+                column,
+                error,
+            } => Error::at(
+                self.source,
+                self.source_offset(offset_of(processed, line, column)),
+                *error,
+            ),
+            error => error,
+        }
+    }
+}
+
+/// Returns the 1-based line and column of the byte `offset` in `text`.
+/// Columns are counted in characters.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Returns the byte offset of the 1-based `line` and `column` in `text`.
+fn offset_of(text: &str, line: usize, column: usize) -> usize {
+    let line_start = text
+        .match_indices('\n')
+        .nth(line.saturating_sub(2))
+        .filter(|_| line > 1)
+        .map_or(0, |(index, _)| index + 1);
+    text[line_start..]
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(text.len(), |(index, _)| line_start + index)
+}
+
+#[test]
+fn error_location_tests() {
+    let file = File::default();
+    let context = Context {
+        profiles: &[],
+        file: &file,
+        delimiters: &ReferenceDelimiters::default(),
+        package: None,
+    };
+    let markdown =
+        "# Title\n$if release$\nrelease\n$endif$\nü $if !release$x $missing.rs:a$$endif$\n";
+    let Err(Error::Markdown {
+        line,
+        column,
+        error,
+    }) = process_markdown(
+        markdown,
+        Path::new("."),
+        &mut HashMap::new(),
+        &HashMap::new(),
+        context,
+    )
+    else {
+        unreachable!("error not located")
+    };
+    assert_eq!((line, column), (5, 18));
+    assert!(matches!(*error, Error::SnippetNotFound(_)));
+
+    let Err(error) = evaluate_conditionals("a\n$if release$\n$if x$\n$endif$\n", context) else {
+        unreachable!("unclosed block not detected")
+    };
+    assert_eq!(
+        error.to_string(),
+        "2:1: A conditional block's if, else, and endif directives are mismatched"
+    );
+
+    for (line, column) in [(1, 1), (1, 3), (2, 1), (3, 2)] {
+        let text = "ab\ncd\néf";
+        let offset = offset_of(text, line, column);
+        assert_eq!(line_column(text, offset), (line, column));
+    }
+}
+
+#[test]
+fn conditionals_tests() {
+    let file = File {
+        profiles: vec![String::from("github")],
+        ..File::default()
+    };
+    let delimiters = ReferenceDelimiters::default();
+    let profiles = GenerateOptions::default()
+        .with_release(true)
+        .profiles_for(&file);
+    let context = Context {
+        profiles: &profiles,
+        file: &file,
+        delimiters: &delimiters,
+        package: None,
+    };
+    assert_eq!(
+        evaluate_conditionals(
+            "a\n$if for_docs$\ndocs\n$else$\n$if release$\nrelease $NAME$\n$endif$\n$endif$\nb $if !github$x$else$y$endif$ $$\n",
+            context,
+        )
+        .unwrap()
+        .0,
+        "a\nrelease $NAME$\nb y $$\n"
+    );
+    assert!(matches!(
+        evaluate_conditionals("$if release$", context),
+        Err(Error::Markdown { error, .. }) if matches!(*error, Error::MalformedConditional)
+    ));
+    assert!(matches!(
+        evaluate_conditionals("$endif$", context),
+        Err(Error::Markdown { error, .. }) if matches!(*error, Error::MalformedConditional)
+    ));
 }
 
-fn process_markdown(
-    markdown: &str,
-    base_dir: &Path,
-    snippets: &mut HashMap<String, String>,
-    glossary: &HashMap<String, Term>,
-    context: Context,
-) -> Result<String, Error> {
-    let expanded = replace_references(markdown, base_dir, snippets, glossary, context)?;
-    preprocess_rust_codeblocks(&expanded)
+#[test]
+fn verbatim_conditionals_tests() {
+    let file = File {
+        verbatim_code: true,
+        ..File::default()
+    };
+    let delimiters = ReferenceDelimiters::default();
+    let profiles = GenerateOptions::default()
+        .with_release(true)
+        .profiles_for(&file);
+    assert_eq!(
+        process_markdown(
+            "Run `echo $HOME`.\n$if release$\nrelease\n$else$\ndebug\n$endif$\n```sh\n$rustme: if release$\ncargo build --release\n$rustme: endif$\n```\n",
+            Path::new("."),
+            &mut HashMap::new(),
+            &HashMap::new(),
+            Context {
+                profiles: &profiles,
+                file: &file,
+                delimiters: &delimiters,
+                package: None,
+            },
+        )
+        .unwrap(),
+        "Run `echo $HOME`.\nrelease\n```sh\ncargo build --release\n```\n"
+    );
+}
+
+#[test]
+fn hidden_lines_for_docs_tests() {
+    let markdown = "```rust\n# use std::fs;\nfs::read(\"a\");\n```\n";
+    let process = |file: &File| {
+        process_markdown(
+            markdown,
+            Path::new("."),
+            &mut HashMap::new(),
+            &HashMap::new(),
+            Context {
+                profiles: &[],
+                file,
+                delimiters: &ReferenceDelimiters::default(),
+                package: None,
+            },
+        )
+        .unwrap()
+    };
+    assert_eq!(
+        process(&File::default()),
+        "```rust\nfs::read(\"a\");\n```\n"
+    );
+    let for_docs = File {
+        for_docs: true,
+        ..File::default()
+    };
+    assert_eq!(process(&for_docs), markdown);
 }
 
 fn load_snippet<'a>(
@@ -408,8 +2574,6 @@ fn load_snippets(
     disk_path: &Path,
     snippets: &mut HashMap<String, String>,
 ) -> Result<(), Error> {
-    const SNIPPET_START: &str = "begin rustme snippet:";
-    const SNIPPET_END: &str = "end rustme snippet";
     let contents = match std::fs::read_to_string(disk_path) {
         Ok(contents) => contents,
         Err(err) => {
@@ -419,8 +2583,22 @@ fn load_snippets(
             return Err(Error::from(err));
         }
     };
+    parse_snippets(ref_path, &contents, snippets)
+}
+
+fn parse_snippets(
+    ref_path: &str,
+    contents: &str,
+    snippets: &mut HashMap<String, String>,
+) -> Result<(), Error> {
+    const SNIPPET_START: &str = "begin rustme snippet:";
+    const SNIPPET_END: &str = "end rustme snippet";
+    const SKIP_START: &str = "rustme: skip-start";
+    const SKIP_END: &str = "rustme: skip-end";
     let mut current_snippet = Vec::new();
     let mut current_snippet_name = None;
+    let mut whole_file = Vec::new();
+    let mut skipping = false;
     for line in contents.lines() {
         if let Some(phrase_start) = line.find(SNIPPET_START) {
             current_snippet_name = Some(
@@ -431,12 +2609,17 @@ fn load_snippets(
                     .unwrap(),
             );
             current_snippet = Vec::default();
+            whole_file.push(Cow::Borrowed(line));
         } else if line.contains(SNIPPET_END) {
-            if let Some(name) = current_snippet_name.take() {
-                remove_shared_prefix(&mut current_snippet);
-                let contents = current_snippet.join("\n");
+            if let (Some(name), false) = (current_snippet_name.take(), skipping) {
+                let mut lines = current_snippet
+                    .iter()
+                    .map(AsRef::as_ref)
+                    .collect::<Vec<&str>>();
+                remove_shared_prefix(&mut lines);
+                let contents = lines.join("\n");
                 if snippets
-                    .insert(format!("{}:{}", ref_path, name), contents)
+                    .insert(format!("{ref_path}:{name}"), contents)
                     .is_some()
                 {
                     return Err(Error::SnippetAlreadyDefined(name.to_string()));
@@ -444,83 +2627,111 @@ fn load_snippets(
             } else {
                 return Err(Error::MalformedSnippet);
             }
-        } else if current_snippet_name.is_some() {
-            current_snippet.push(line);
+            whole_file.push(Cow::Borrowed(line));
+        } else if let Some(phrase_start) = line.find(SKIP_START) {
+            if skipping {
+                return Err(Error::MalformedSnippet);
+            }
+            skipping = true;
+            // The placeholder keeps everything before the marker, such as the
+            // indentation and comment syntax, followed by either the text
+            // after the marker or "...".
+            let placeholder = line[phrase_start + SKIP_START.len()..].trim();
+            let placeholder = if placeholder.is_empty() {
+                "..."
+            } else {
+                placeholder
+            };
+            let placeholder = format!("{}{placeholder}", &line[..phrase_start]);
+            if current_snippet_name.is_some() {
+                current_snippet.push(Cow::Owned(placeholder.clone()));
+            }
+            whole_file.push(Cow::Owned(placeholder));
+        } else if line.contains(SKIP_END) {
+            if !skipping {
+                return Err(Error::MalformedSnippet);
+            }
+            skipping = false;
+        } else if !skipping {
+            if current_snippet_name.is_some() {
+                current_snippet.push(Cow::Borrowed(line));
+            }
+            whole_file.push(Cow::Borrowed(line));
         }
     }
 
-    // Allow referring to an entire file as a snippet.
-    snippets.insert(ref_path.to_string(), contents);
-
-    Ok(())
-}
-
-struct StrByteIterator<'a> {
-    remaining: &'a [u8],
-}
-
-impl<'a> StrByteIterator<'a> {
-    pub const fn new(value: &'a str) -> Self {
-        Self {
-            remaining: value.as_bytes(),
-        }
+    if skipping {
+        return Err(Error::MalformedSnippet);
     }
 
-    pub fn try_read(&mut self, compare_against: &str) -> bool {
-        if self.remaining.starts_with(compare_against.as_bytes()) {
-            let (_, tail) = self.remaining.split_at(compare_against.len());
-            self.remaining = tail;
-            true
-        } else {
-            false
-        }
+    // Allow referring to an entire file as a snippet.
+    let mut whole_file = whole_file.join("\n");
+    if contents.ends_with('\n') {
+        whole_file.push('\n');
     }
+    snippets.insert(ref_path.to_string(), whole_file);
 
-    pub fn read_until(
-        &mut self,
-        mut cb: impl FnMut(u8) -> bool,
-        include_last_byte: bool,
-    ) -> Result<&'a str, Error> {
-        for (index, byte) in self.remaining.iter().copied().enumerate() {
-            // Do not offer non-ascii characters to the callback. This could
-            // allow splitting inside of a unicode code point.
-            if byte < 128 && cb(byte) {
-                let (read, tail) = if include_last_byte {
-                    self.remaining.split_at(index + 1)
-                } else {
-                    self.remaining.split_at(index)
-                };
-                self.remaining = tail;
-                return Ok(std::str::from_utf8(read)?);
-            }
-        }
-
-        let result = self.remaining;
-        self.remaining = b"";
-        Ok(std::str::from_utf8(result)?)
-    }
+    Ok(())
+}
 
-    pub fn read_until_char(&mut self, ch: u8) -> Result<&'a str, Error> {
-        self.read_until(|byte| byte == ch, false)
-    }
+#[test]
+fn snippet_elision_tests() {
+    let mut snippets = HashMap::new();
+    parse_snippets(
+        "main.rs",
+        "fn main() {
+    // begin rustme snippet: example
+    // rustme: skip-start
+    let config = setup();
+    // rustme: skip-end
+    run(config);
+    # rustme: skip-start (setup omitted)
+    cleanup();
+    # rustme: skip-end
+    // end rustme snippet
+}",
+        &mut snippets,
+    )
+    .unwrap();
+    assert_eq!(
+        snippets["main.rs:example"],
+        "// ...\nrun(config);\n# (setup omitted)"
+    );
 
-    pub fn read_line(&mut self) -> Result<&'a str, Error> {
-        self.read_until(|ch| ch == b'\n', true)
-    }
-}
+    let mut snippets = HashMap::new();
+    assert!(matches!(
+        parse_snippets(
+            "main.rs",
+            "// begin rustme snippet: a\n// rustme: skip-start\n// end rustme snippet",
+            &mut snippets,
+        ),
+        Err(Error::MalformedSnippet)
+    ));
 
-impl<'a> Iterator for StrByteIterator<'a> {
-    type Item = u8;
+    // Markers outside of a named snippet elide lines from the whole file.
+    let mut snippets = HashMap::new();
+    parse_snippets(
+        "main.rs",
+        "use std::env;
+// rustme: skip-start
+mod internal;
+// rustme: skip-end
+fn main() {}
+",
+        &mut snippets,
+    )
+    .unwrap();
+    assert_eq!(snippets["main.rs"], "use std::env;\n// ...\nfn main() {}\n");
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.remaining.is_empty() {
-            None
-        } else {
-            let (next, tail) = self.remaining.split_at(1);
-            self.remaining = tail;
-            next.get(0).copied()
-        }
-    }
+    let mut snippets = HashMap::new();
+    assert!(matches!(
+        parse_snippets(
+            "main.rs",
+            "// rustme: skip-start\nfn main() {}",
+            &mut snippets,
+        ),
+        Err(Error::MalformedSnippet)
+    ));
 }
 
 /// A mapping of replacements that can be used within the files using `$name$`
@@ -528,8 +2739,10 @@ impl<'a> Iterator for StrByteIterator<'a> {
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Glossary {
-    /// An external glossary. The contained value should be a valid Url to a
-    /// Ron-encoded `HashMap<String, String>`.
+    /// An external glossary. The contained value should be a valid Url or
+    /// path to a map of [`Term`]s encoded as Ron, TOML, or JSON. The format
+    /// is detected from the `.ron`, `.toml`, or `.json` extension, falling
+    /// back to the `Content-Type` of the response, and otherwise Ron.
     External(String),
     /// An inline glossary.
     Inline(HashMap<String, Term>),
@@ -538,8 +2751,8 @@ pub enum Glossary {
 impl Glossary {
     fn location(&self) -> &str {
         match self {
-            Glossary::External(location) => location,
-            Glossary::Inline(_) => "(inline)",
+            Self::External(location) => location,
+            Self::Inline(_) => "(inline)",
         }
     }
 }
@@ -550,7 +2763,9 @@ impl Glossary {
 pub enum Term {
     /// A term that is the same value in all contexts.
     Static(String),
-    /// A term that has different values based on the output context.
+    /// A term that has different values based on the output context. The
+    /// value of the highest precedence active profile is used, falling back
+    /// to `default`.
     Conditional {
         /// The value to be used when [`File::for_docs`] is true.
         #[serde(default)]
@@ -561,63 +2776,137 @@ pub enum Term {
         /// The value to be used basic output operations.
         #[serde(default)]
         default: Option<String>,
+        /// Values to be used when other profiles are active, such as
+        /// `{"github": "..."}`.
+        #[serde(default)]
+        profiles: BTreeMap<String, String>,
     },
 }
 
 impl Term {
     fn update_with(&mut self, other: Self) {
         *self = match (&self, other) {
-            (_, Term::Static(value)) => Self::Static(value),
+            (_, Self::Static(value)) => Self::Static(value),
             (
-                Term::Static(value),
-                Term::Conditional {
+                Self::Static(value),
+                Self::Conditional {
                     for_docs,
                     release,
                     default,
+                    profiles,
                 },
             ) => Self::Conditional {
                 for_docs,
                 release,
-                default: default.or_else(|| Some(value.to_string())),
+                default: default.or_else(|| Some(value.clone())),
+                profiles,
             },
             (
-                Term::Conditional {
+                Self::Conditional {
                     for_docs,
                     release,
                     default,
+                    profiles,
                 },
-                Term::Conditional {
+                Self::Conditional {
                     for_docs: other_for_docs,
                     release: other_release,
                     default: other_default,
+                    profiles: other_profiles,
                 },
-            ) => Self::Conditional {
-                for_docs: other_for_docs.or_else(|| for_docs.clone()),
-                release: other_release.or_else(|| release.clone()),
-                default: other_default.or_else(|| default.clone()),
-            },
+            ) => {
+                let mut profiles = profiles.clone();
+                profiles.extend(other_profiles);
+                Self::Conditional {
+                    for_docs: other_for_docs.or_else(|| for_docs.clone()),
+                    release: other_release.or_else(|| release.clone()),
+                    default: other_default.or_else(|| default.clone()),
+                    profiles,
+                }
+            }
         }
     }
-    fn to_string(&self, context: Context) -> String {
+
+    fn to_string(&self, context: Context<'_>) -> String {
         match self {
-            Term::Static(value) => value.clone(),
-            Term::Conditional {
+            Self::Static(value) => value.clone(),
+            Self::Conditional {
                 default,
                 release,
-                for_docs: for_docs_value,
-            } => {
-                if let (true, Some(value)) = (context.for_docs, for_docs_value) {
-                    value.clone()
-                } else if let (true, Some(value)) = (context.release, release) {
-                    value.clone()
-                } else {
-                    default.clone().unwrap_or_default()
-                }
-            }
+                for_docs,
+                profiles,
+            } => context
+                .profiles
+                .iter()
+                .find_map(|profile| {
+                    match profile.as_str() {
+                        "for_docs" => for_docs.as_ref(),
+                        "release" => release.as_ref(),
+                        _ => None,
+                    }
+                    .or_else(|| profiles.get(profile))
+                })
+                .or(default.as_ref())
+                .cloned()
+                .unwrap_or_default(),
         }
     }
 }
 
+#[test]
+fn definitions_tests() {
+    let mut glossary = HashMap::new();
+    glossary.insert(
+        String::from("VERSION"),
+        Term::Conditional {
+            for_docs: None,
+            release: Some(String::from("0.1.0")),
+            default: None,
+            profiles: BTreeMap::new(),
+        },
+    );
+    GenerateOptions::default()
+        .with_definition("VERSION", "0.2.0")
+        .with_definition("DATE", "2021-11-01")
+        .apply_definitions(&mut glossary);
+    assert!(matches!(&glossary["VERSION"], Term::Static(version) if version == "0.2.0"));
+    assert!(matches!(&glossary["DATE"], Term::Static(date) if date == "2021-11-01"));
+}
+
+#[test]
+fn term_profiles_tests() {
+    let glossary: BTreeMap<String, Term> = ron::from_str(
+        r#"{
+            "DOCS": (
+                default: "https://docs.rs/rustme",
+                for_docs: "crate",
+                profiles: {
+                    "github": "https://khonsulabs.github.io/rustme/main/rustme/",
+                },
+            ),
+        }"#,
+    )
+    .unwrap();
+    let file = File::default();
+    let delimiters = ReferenceDelimiters::default();
+    let value = |profiles: &[&str]| {
+        let profiles = profiles.iter().map(ToString::to_string).collect::<Vec<_>>();
+        glossary["DOCS"].to_string(Context {
+            profiles: &profiles,
+            file: &file,
+            delimiters: &delimiters,
+            package: None,
+        })
+    };
+    assert_eq!(value(&[]), "https://docs.rs/rustme");
+    assert_eq!(value(&["mdbook"]), "https://docs.rs/rustme");
+    assert_eq!(
+        value(&["github"]),
+        "https://khonsulabs.github.io/rustme/main/rustme/"
+    );
+    assert_eq!(value(&["for_docs", "github"]), "crate");
+}
+
 #[test]
 fn test_no_glossary() {
     let configuration: Configuration = ron::from_str(
@@ -631,7 +2920,51 @@ fn test_no_glossary() {
         "#,
     )
     .unwrap();
-    println!("Parsed: {:?}", configuration);
+    println!("Parsed: {configuration:?}");
+}
+
+#[test]
+fn test_conditional_sections() {
+    let configuration: Configuration = ron::from_str(
+        r#"
+        Configuration(
+            files: {
+                "README.md": ["a", (path: "badges.md", condition: "!for_docs")],
+                "docs.md": (
+                    for_docs: true,
+                    sections: [
+                        "a",
+                        (path: "badges.md", condition: "!for_docs"),
+                        (path: "docs.md", only_for_docs: true),
+                        (path: "release.md", only_release: true),
+                    ],
+                ),
+            }
+        )
+        "#,
+    )
+    .unwrap();
+    let delimiters = ReferenceDelimiters::default();
+    let included = |name: &str, release: bool| {
+        let file = File::from(&configuration.files[name]);
+        let profiles = GenerateOptions::default()
+            .with_release(release)
+            .profiles_for(&file);
+        let context = Context {
+            profiles: &profiles,
+            file: &file,
+            delimiters: &delimiters,
+            package: None,
+        };
+        file.sections
+            .iter()
+            .filter(|section| section.is_included(context))
+            .map(|section| section.path().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(included("README.md", false), ["a", "badges.md"]);
+    assert_eq!(included("docs.md", false), ["a", "docs.md"]);
+    assert_eq!(included("docs.md", true), ["a", "docs.md", "release.md"]);
 }
 
 #[test]
@@ -652,7 +2985,310 @@ fn test_glossary() {
         "#,
     )
     .unwrap();
-    println!("Parsed: {:?}", configuration);
+    println!("Parsed: {configuration:?}");
+}
+
+/// A directory for test fixtures that is removed when dropped, even if the
+/// test panics.
+#[cfg(test)]
+struct TestDirectory(PathBuf);
+
+#[cfg(test)]
+impl TestDirectory {
+    fn new(name: &str) -> Self {
+        let directory = std::env::temp_dir().join(format!("rustme-{name}-{}", std::process::id()));
+        if directory.exists() {
+            fs::remove_dir_all(&directory).unwrap();
+        }
+        fs::create_dir_all(&directory).unwrap();
+        Self(directory)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDirectory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TestDirectory {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn extends_tests() {
+    let directory = TestDirectory::new("extends");
+    fs::create_dir_all(directory.join("shared")).unwrap();
+    fs::write(
+        directory.join("shared/org.ron"),
+        r#"Configuration(
+            extends: Some("base.toml"),
+            files: {
+                "LICENSE": ["license.md"],
+                "README.md": ["org-header.md"],
+            },
+            glossaries: [{"ORG": "khonsulabs"}],
+        )"#,
+    )
+    .unwrap();
+    fs::write(
+        directory.join("shared/base.toml"),
+        "glossaries = [{ BASE = \"base\" }]\n\n[files]\n\"CODE_OF_CONDUCT.md\" = [\"coc.md\"]\n",
+    )
+    .unwrap();
+
+    let mut configuration: Configuration = ron::from_str(
+        r#"Configuration(
+            extends: Some("shared/org.ron"),
+            files: {
+                "README.md": ["header.md"],
+            },
+            glossaries: [{"ORG": "overridden"}],
+        )"#,
+    )
+    .unwrap();
+    configuration.relative_to = directory.to_path_buf();
+    let resolved = configuration
+        .resolve_extends(&mut Cache::default())
+        .unwrap();
+
+    assert!(resolved.extends.is_none());
+    let files = resolved.files.keys().cloned().collect::<Vec<_>>();
+    assert_eq!(files, ["CODE_OF_CONDUCT.md", "LICENSE", "README.md"]);
+    assert_eq!(
+        File::from(&resolved.files["README.md"]).sections[0].path(),
+        "header.md"
+    );
+    let locations = resolved
+        .glossaries
+        .iter()
+        .map(|glossary| match glossary {
+            Glossary::Inline(terms) => terms.keys().next().unwrap().clone(),
+            Glossary::External(location) => location.clone(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(locations, ["BASE", "ORG", "ORG"]);
+    let Glossary::Inline(terms) = &resolved.glossaries[2] else {
+        unreachable!()
+    };
+    assert!(matches!(&terms["ORG"], Term::Static(value) if value == "overridden"));
+
+    // Discovery produces relative paths, which must only be joined once.
+    let mut cache = Cache::default();
+    cache.insert_generated(
+        Path::new("./project/.rustme/shared/org.ron"),
+        String::from(r#"Configuration(glossaries: [{"ORG": "khonsulabs"}])"#),
+    );
+    let mut configuration: Configuration =
+        ron::from_str(r#"Configuration(extends: Some("shared/org.ron"))"#).unwrap();
+    configuration.relative_to = PathBuf::from("./project/.rustme");
+    let resolved = configuration.resolve_extends(&mut cache).unwrap();
+    assert_eq!(resolved.glossaries.len(), 1);
+}
+
+impl Error {
+    /// Returns `error` located at the byte `offset` of `markdown`.
+    fn at(markdown: &str, offset: usize, error: Self) -> Self {
+        let (line, column) = line_column(markdown, offset);
+        Self::Markdown {
+            line,
+            column,
+            error: Box::new(error),
+        }
+    }
+}
+
+fn display_section(section: &str, error: &Error) -> String {
+    match error {
+        Error::Markdown {
+            line,
+            column,
+            error,
+        } => format!("{section}:{line}:{column}: {error}"),
+        error => format!("{section}: {error}"),
+    }
+}
+
+/// Formats `failures` grouped by configuration and output file.
+fn display_failures(failures: &[Failure]) -> String {
+    let mut grouped = IndexMap::<&Path, IndexMap<Option<&Path>, Vec<&Error>>>::new();
+    for failure in failures {
+        grouped
+            .entry(&failure.configuration)
+            .or_default()
+            .entry(failure.output.as_deref())
+            .or_default()
+            .push(&failure.error);
+    }
+
+    let mut lines = vec![if failures.len() == 1 {
+        String::from("1 error occurred")
+    } else {
+        format!("{} errors occurred", failures.len())
+    }];
+    for (configuration, outputs) in grouped {
+        lines.push(format!("{}:", configuration.display()));
+        for (output, errors) in outputs {
+            let indent = if let Some(output) = output {
+                lines.push(format!("  {}:", output.display()));
+                "    "
+            } else {
+                "  "
+            };
+            for error in errors {
+                lines.push(format!("{indent}{error}"));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+fn is_url(resource: &str) -> bool {
+    resource.starts_with("http://") || resource.starts_with("https://")
+}
+
+fn parent_directory(path: &Path) -> Result<PathBuf, Error> {
+    path.parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| Error::Io(std::io::Error::from(ErrorKind::NotFound)))
+}
+
+#[test]
+fn files_order_tests() {
+    let configuration: Configuration = ron::from_str(
+        r#"Configuration(
+            files: {
+                "z.md": ["a"],
+                "b.md": ["b"],
+                "m.md": ["c"],
+            },
+        )"#,
+    )
+    .unwrap();
+    assert_eq!(
+        configuration.files.keys().collect::<Vec<_>>(),
+        ["z.md", "b.md", "m.md"]
+    );
+
+    let configuration: Configuration =
+        toml::from_str("[files]\n\"z.md\" = [\"a\"]\n\"b.md\" = [\"b\"]\n").unwrap();
+    assert_eq!(
+        configuration.files.keys().collect::<Vec<_>>(),
+        ["z.md", "b.md"]
+    );
+}
+
+#[test]
+fn member_files_tests() {
+    let directory = TestDirectory::new("members");
+    fs::create_dir_all(directory.join("crates/a")).unwrap();
+    fs::create_dir_all(directory.join("docs")).unwrap();
+    fs::write(
+        directory.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"crates/*\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("crates/a/Cargo.toml"),
+        "[package]\nname = \"a\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+
+    // The configuration is not at the workspace root, but `{member}` paths
+    // are still relative to it.
+    let mut configuration: Configuration = ron::from_str(
+        r#"Configuration(
+            files: {
+                "{member}/README.md": ["header.md", "{member}/README-source.md"],
+            },
+        )"#,
+    )
+    .unwrap();
+    configuration.relative_to = directory.join("docs");
+    let planned = configuration.plan().unwrap();
+    let root = directory.canonicalize().unwrap();
+    assert_eq!(planned.len(), 1);
+    assert_eq!(planned[0].output, root.join("crates/a/README.md"));
+    assert_eq!(
+        planned[0].inputs,
+        [
+            directory.join("docs/header.md"),
+            root.join("crates/a/README-source.md")
+        ]
+    );
+}
+
+#[test]
+fn unparsable_manifest_tests() {
+    let directory = TestDirectory::new("unparsable-manifest");
+    fs::create_dir_all(directory.join("docs")).unwrap();
+    fs::write(directory.join("Cargo.toml"), "[package").unwrap();
+
+    let mut configuration: Configuration =
+        ron::from_str(r#"Configuration(files: {"../README.md": ["README.md"]})"#).unwrap();
+    configuration.relative_to = directory.join("docs");
+    let planned = configuration.plan().unwrap();
+    assert!(planned[0].package.is_none());
+}
+
+#[test]
+fn manifest_configuration_tests() {
+    let manifest = Manifest::parse(String::from(
+        r#"
+        [package]
+        name = "example"
+
+        [package.metadata.rustme]
+        glossaries = [{ TEST = "SUCCESS" }]
+
+        [package.metadata.rustme.files]
+        "README.md" = ["header.md", { path = "badges.md", condition = "!for_docs" }]
+        "CONTRIBUTING.md" = ["contributing.md"]
+        "#,
+    ))
+    .unwrap();
+    let configuration = Configuration::from_manifest(&manifest).unwrap().unwrap();
+    assert_eq!(
+        configuration.files.keys().collect::<Vec<_>>(),
+        ["README.md", "CONTRIBUTING.md"]
+    );
+    let File { sections, .. } = File::from(&configuration.files["README.md"]);
+    assert_eq!(sections.len(), 2);
+    assert_eq!(sections[1].path(), "badges.md");
+    assert_eq!(configuration.glossaries.len(), 1);
+
+    let workspace = Manifest::parse(String::from(
+        r#"
+        [workspace.metadata.rustme.files]
+        "README.md" = ["header.md"]
+        "#,
+    ))
+    .unwrap();
+    assert!(Configuration::from_manifest(&workspace).unwrap().is_some());
+
+    let without = Manifest::parse(String::from("[package]\nname = \"example\"\n")).unwrap();
+    assert!(Configuration::from_manifest(&without).unwrap().is_none());
 }
 
 /// All errors that `rustme` can return.
@@ -662,18 +3298,113 @@ pub enum Error {
     /// No configuration was found.
     #[error("no configuration found")]
     NoConfiguration,
-    /// A snippet reference is missing its closing `$`.
-    #[error("A snippet reference is missing its closing $")]
+    /// The configuration named by [`Configuration::extends`] was not found.
+    #[error("extended configuration not found: {0}")]
+    ExtendedConfigurationNotFound(String),
+    /// A configuration extends itself, either directly or through another
+    /// configuration.
+    #[error("configuration extends itself: {0}")]
+    CyclicExtends(String),
+    /// An error occurred at a position within a section's markdown. Lines and
+    /// columns start at 1, and columns are counted in characters.
+    #[error("{line}:{column}: {error}")]
+    Markdown {
+        /// The line the error occurred on.
+        line: usize,
+        /// The column the error occurred at.
+        column: usize,
+        /// The error that occurred.
+        error: Box<Self>,
+    },
+    /// An error occurred while processing a section of an output file.
+    #[error("{}", display_section(section, error))]
+    Section {
+        /// The section's path or Url.
+        section: String,
+        /// The error that occurred.
+        error: Box<Self>,
+    },
+    /// An error occurred while generating an output file.
+    #[error("{}: {error}", output.display())]
+    Output {
+        /// The path of the output file.
+        output: PathBuf,
+        /// The error that occurred.
+        error: Box<Self>,
+    },
+    /// An error occurred while processing a configuration.
+    #[error("{}: {error}", configuration.display())]
+    Configuration {
+        /// The path of the configuration.
+        configuration: PathBuf,
+        /// The error that occurred.
+        error: Box<Self>,
+    },
+    /// Multiple errors occurred while generating with
+    /// [`GenerateOptions::keep_going`].
+    #[error("{}", display_failures(.0))]
+    Multiple(Vec<Failure>),
+    /// A glob used to discover configurations is invalid.
+    #[error("invalid glob: {0}")]
+    InvalidGlob(String),
+    /// Two configurations, or two files in the same configuration, generate
+    /// the same output file.
+    #[error(
+        "{} is generated by both {} and {}",
+        output.display(),
+        first.display(),
+        second.display()
+    )]
+    OutputConflict {
+        /// The path of the output file.
+        output: PathBuf,
+        /// The configuration that first declared the output file.
+        first: PathBuf,
+        /// The configuration that also declared the output file.
+        second: PathBuf,
+    },
+    /// A file was not generated because a file it uses as a section, listed
+    /// here, failed to generate.
+    #[error("skipped because {} failed", .0.display())]
+    DependencyFailed(PathBuf),
+    /// Generated files depend on each other in a cycle. The paths of the
+    /// files in the cycle are listed in order, starting and ending with the
+    /// same file.
+    #[error("generated files depend on each other: {}", display_paths(.0))]
+    CyclicDependency(Vec<PathBuf>),
+    /// A file uses `{member}`, but the configuration is not located within a
+    /// Cargo workspace.
+    #[error("no cargo workspace found for a file using {{member}}")]
+    WorkspaceNotFound,
+    /// A [`ReferenceDelimiters`] has an empty `start` or `end`.
+    #[error("reference delimiters must not be empty")]
+    EmptyReferenceDelimiter,
+    /// A reference is missing its end delimiter.
+    #[error("A reference is missing its end delimiter")]
     MalformedSnippetReference,
     /// A mismatch of snippet begins and ends.
     #[error("A mismatch of snippet begins and ends")]
     MalformedSnippet,
+    /// An `if`, `else`, or `endif` directive was not matched.
+    #[error("A conditional block's if, else, and endif directives are mismatched")]
+    MalformedConditional,
     /// A rust code block was not able to be parsed.
     #[error("A rust code block was not able to be parsed")]
     MalformedCodeBlock,
     /// A snippet was already defined.
     #[error("snippet already defined: {0}")]
     SnippetAlreadyDefined(String),
+    /// A snippet filter could not be parsed.
+    #[error("invalid snippet filter: {0}")]
+    InvalidSnippetFilter(String),
+    /// A built-in reference, such as `cargo:dependency`, is unknown or has
+    /// invalid arguments.
+    #[error("invalid built-in reference: {0}")]
+    InvalidBuiltinReference(String),
+    /// A built-in reference requires a Cargo package, but the configuration
+    /// is not located within one.
+    #[error("no cargo package found for built-in reference: {0}")]
+    PackageNotFound(String),
     /// A snippet was not found.
     #[error("snippet not found: {0}")]
     SnippetNotFound(String),
@@ -686,6 +3417,12 @@ pub enum Error {
     /// A [Ron](https://github.com/ron-rs/ron) error.
     #[error("ron error: {0}")]
     Ron(#[from] ron::Error),
+    /// A [Toml](https://toml.io/) error.
+    #[error("toml error: {0}")]
+    Toml(#[from] toml::de::Error),
+    /// A [JSON](https://www.json.org/) error.
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
     /// An error requesting an Http resource.
     #[error("http error: {0}")]
     Http(#[from] ureq::Error),
@@ -714,6 +3451,63 @@ impl From<FromUtf8Error> for Error {
     }
 }
 
+fn has_rustme_metadata(manifest: &Path) -> Result<bool, Error> {
+    let manifest = Manifest::parse(fs::read_to_string(manifest)?)?;
+    Ok(manifest.rustme_metadata::<toml::Value>()?.is_some())
+}
+
+#[test]
+fn discover_configurations_tests() {
+    let directory = TestDirectory::new("discover");
+    for path in [
+        "",
+        "crates/a",
+        "crates/a/target/package",
+        "node_modules/b",
+        "vendor/c",
+        "ignored",
+    ] {
+        fs::create_dir_all(directory.join(path)).unwrap();
+        fs::write(directory.join(path).join(".rustme.ron"), "").unwrap();
+    }
+    fs::write(directory.join(".gitignore"), "ignored/\n").unwrap();
+    // Manifests that fail to parse are skipped rather than stopping discovery.
+    fs::write(directory.join("crates/a/Cargo.toml"), "[package").unwrap();
+
+    let discover = |options: &DiscoveryOptions| {
+        discover_configurations(&directory, options)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(&directory)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect::<Vec<_>>()
+    };
+    let default = discover(&DiscoveryOptions::default());
+    let excluded = discover(&DiscoveryOptions::default().with_exclude("vendor/**"));
+    let shallow = discover(&DiscoveryOptions::default().with_max_depth(1));
+    let included = discover(
+        &DiscoveryOptions::default()
+            .with_ignore_files(false)
+            .with_include("i*/*"),
+    );
+
+    assert_eq!(
+        default,
+        [
+            ".rustme.ron",
+            "crates/a/.rustme.ron",
+            "vendor/c/.rustme.ron"
+        ]
+    );
+    assert_eq!(excluded, [".rustme.ron", "crates/a/.rustme.ron"]);
+    assert_eq!(shallow, [".rustme.ron"]);
+    assert_eq!(included, ["ignored/.rustme.ron"]);
+}
+
 /// Generates all `RustMe` configurations found within the current directory.
 ///
 /// ## Errors
@@ -724,6 +3518,17 @@ pub fn generate(release: bool) -> Result<(), Error> {
     generate_in_directory(Path::new("."), release)
 }
 
+/// Generates all `RustMe` configurations found within the current directory
+/// using `options`.
+///
+/// ## Errors
+///
+/// - Returns any errors occurred processing an individual configuration.
+/// - Returns [`Error::NoConfiguration`] if no configurations were found.
+pub fn generate_with(options: &GenerateOptions) -> Result<(), Error> {
+    generate_in_directory_with(Path::new("."), options)
+}
+
 /// Generates all `RustMe` configurations found within `directory`.
 ///
 /// ## Errors
@@ -731,37 +3536,132 @@ pub fn generate(release: bool) -> Result<(), Error> {
 /// - Returns any errors occurred processing an individual configuration.
 /// - Returns [`Error::NoConfiguration`] if no configurations were found.
 pub fn generate_in_directory(directory: &Path, release: bool) -> Result<(), Error> {
+    generate_in_directory_with(directory, &GenerateOptions::default().with_release(release))
+}
+
+/// Generates all `RustMe` configurations found within `directory` using
+/// `options`. Configurations are found using [`discover_configurations`]
+/// with [`GenerateOptions::discovery`].
+///
+/// ## Errors
+///
+/// - Returns any errors occurred processing an individual configuration.
+/// - Returns [`Error::NoConfiguration`] if no configurations were found.
+pub fn generate_in_directory_with(
+    directory: &Path,
+    options: &GenerateOptions,
+) -> Result<(), Error> {
     let mut cache = Cache::default();
-    let mut found_a_config = false;
-    let initial_depth = directory.components().count();
-    for entry in WalkDir::new(directory).into_iter().filter_map(Result::ok) {
-        if entry
-            .path()
-            .components()
-            .nth(initial_depth)
-            .map(|c| c.as_os_str() == "target")
-            .unwrap_or_default()
-        {
-            continue;
+    let mut failures = Failures::new(options.keep_going);
+    let discovered = discover_configurations(directory, &options.discovery)?;
+    if discovered.is_empty() {
+        return Err(Error::NoConfiguration);
+    }
+
+    let mut configurations = Vec::new();
+    for config_path in discovered {
+        println!("Processing {}", config_path.display());
+        match load_and_plan(&config_path, &mut cache) {
+            Ok((configuration, files)) => configurations.push(PlannedConfiguration {
+                source: config_path,
+                configuration,
+                files,
+            }),
+            Err(err) => failures.record_in_context(&config_path, None, err)?,
         }
+    }
+
+    generate_planned(&configurations, options, &mut cache, &mut failures)?;
+    failures.finish()
+}
+
+fn load_and_plan(
+    config_path: &Path,
+    cache: &mut Cache,
+) -> Result<(Configuration, Vec<PlannedFile>), Error> {
+    let config = if config_path
+        .file_name()
+        .is_some_and(|name| name == "Cargo.toml")
+    {
+        Configuration::load_from_manifest(config_path)?.ok_or(Error::NoConfiguration)?
+    } else {
+        Configuration::load(config_path)?
+    };
+    let configuration = config.resolve_extends(cache)?;
+    let files = configuration.plan()?;
+    Ok((configuration, files))
+}
+
+/// Returns the paths of all `RustMe` configurations found within `directory`.
+///
+/// A configuration is either a `.rustme.ron` file, a `.rustme/config.ron`
+/// file, or a `Cargo.toml` containing `[package.metadata.rustme]` or
+/// `[workspace.metadata.rustme]`. The paths are sorted by file name. A
+/// `Cargo.toml` that cannot be read or parsed is skipped with a warning.
+///
+/// ## Errors
+///
+/// - Returns [`Error::InvalidGlob`] if a glob in `options` is invalid.
+pub fn discover_configurations(
+    directory: &Path,
+    options: &DiscoveryOptions,
+) -> Result<Vec<PathBuf>, Error> {
+    let include = glob_set(&options.include)?;
+    let exclude = glob_set(&options.exclude)?;
+    let relative = {
+        let directory = directory.to_path_buf();
+        move |path: &Path| path.strip_prefix(&directory).unwrap_or(path).to_path_buf()
+    };
+
+    let mut walker = WalkBuilder::new(directory);
+    walker
+        .standard_filters(false)
+        .git_ignore(options.respect_ignore_files)
+        .git_exclude(options.respect_ignore_files)
+        .ignore(options.respect_ignore_files)
+        .parents(options.respect_ignore_files)
+        .require_git(false)
+        .max_depth(options.max_depth)
+        .sort_by_file_name(Ord::cmp);
+    let filter_relative = relative.clone();
+    walker.filter_entry(move |entry| {
+        let is_dir = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir());
+        entry.depth() == 0
+            || !(is_dir
+                && SKIPPED_DIRECTORIES
+                    .iter()
+                    .any(|skipped| entry.file_name() == *skipped)
+                || exclude.is_match(filter_relative(entry.path())))
+    });
 
+    let mut configurations = Vec::new();
+    for entry in walker.build().filter_map(Result::ok) {
+        let is_dir = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir());
         let config_path = if entry.file_name() == ".rustme.ron" {
             entry.into_path()
-        } else if entry.file_type().is_dir() && entry.file_name() == ".rustme" {
+        } else if is_dir && entry.file_name() == ".rustme" {
             entry.path().join("config.ron")
+        } else if !is_dir && entry.file_name() == "Cargo.toml" {
+            match has_rustme_metadata(entry.path()) {
+                Ok(true) => entry.into_path(),
+                Ok(false) => continue,
+                Err(err) => {
+                    eprintln!("warning: skipping {}: {err}", entry.path().display());
+                    continue;
+                }
+            }
         } else {
             continue;
         };
-        found_a_config = true;
 
-        println!("Processing {:?}", config_path);
-        let config = Configuration::load(config_path)?;
-        config.generate_with_cache(release, &mut cache)?;
+        if options.include.is_empty() || include.is_match(relative(&config_path)) {
+            configurations.push(config_path);
+        }
     }
 
-    if found_a_config {
-        Ok(())
-    } else {
-        Err(Error::NoConfiguration)
-    }
+    Ok(configurations)
 }